@@ -0,0 +1,357 @@
+use std::{
+    fs::{self, File},
+    io::{self, Read},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use scratch_server::{api_error::ApiError, Body, HttpResponse};
+use serde::Serialize;
+
+use super::{
+    file_types::SNIFF_LENGTH,
+    utils::{
+        claim_path, directory_quota, directory_size, file_info, relative_upload_path,
+        release_claim, ConflictPolicy, ResolvedPath, Share, UploadConfig,
+    },
+};
+
+#[derive(Debug, Serialize)]
+struct DeleteResponse {
+    removed: Vec<String>,
+}
+
+/// Deletes the file or directory at the percent-encoded `path` in the share. Directories must
+/// be empty unless `recursive` is set. Responds with the paths of everything removed, contents
+/// before the directory holding them, or `409` for a directory that is not empty.
+pub fn delete_path(share: &Share, path: &str, recursive: bool) -> Result<HttpResponse, ApiError> {
+    let entry = share.resolve_entry(path)?;
+    if entry.path == entry.base {
+        return Err(ApiError::new_with_json(
+            400,
+            "The served directory cannot be deleted",
+        ));
+    }
+    entry.check_writable()?;
+
+    let is_directory = fs::symlink_metadata(&entry.path)?.is_dir();
+    if is_directory && !recursive && fs::read_dir(&entry.path)?.next().is_some() {
+        return Err(ApiError::new_with_json(
+            409,
+            "Directory is not empty, pass recursive=true to delete its contents",
+        ));
+    }
+    let mut removed = Vec::new();
+    remove_entry(&entry.path, &mut removed)?;
+
+    let delete_response = DeleteResponse {
+        removed: removed.iter().map(|path| entry.client_path(path)).collect(),
+    };
+    Ok(HttpResponse::new(
+        Some(Body::Json(serde_json::to_value(delete_response)?)),
+        None,
+        200,
+    ))
+}
+
+/// Removes `path` and, for a directory, everything below it, recording each removed path.
+/// Symlinks are removed themselves and never followed.
+fn remove_entry(path: &Path, removed: &mut Vec<PathBuf>) -> io::Result<()> {
+    if fs::symlink_metadata(path)?.is_dir() {
+        for entry in fs::read_dir(path)? {
+            remove_entry(&entry?.path(), removed)?;
+        }
+        fs::remove_dir(path)?;
+    } else {
+        fs::remove_file(path)?;
+    }
+    removed.push(path.to_path_buf());
+    Ok(())
+}
+
+/// Creates the directory at the percent-encoded `path` in the share. With `parents`, missing
+/// parent directories are created too and an existing directory is not an error, like
+/// `mkdir -p`. Responds with the directory, with `201` when it was created and `200` when it
+/// already existed.
+pub fn create_directory(
+    share: &Share,
+    path: &str,
+    parents: bool,
+) -> Result<HttpResponse, ApiError> {
+    let (dir, path) = destination(share, path)?;
+    let existing = fs::symlink_metadata(&path).ok();
+    if existing
+        .as_ref()
+        .is_some_and(|existing| !parents || !existing.is_dir())
+    {
+        return Err(ApiError::new_with_json(409, "Path already exists"));
+    }
+    let created = match parents {
+        true => fs::create_dir_all(&path),
+        false => fs::create_dir(&path),
+    };
+    created.map_err(|err| match err.kind() {
+        io::ErrorKind::NotFound => ApiError::new_with_json(
+            404,
+            "Parent directory not found, pass parents=true to create it",
+        ),
+        _ => err.into(),
+    })?;
+    Ok(HttpResponse::new(
+        Some(Body::Json(file_info(&dir, &path)?)),
+        None,
+        if existing.is_some() { 200 } else { 201 },
+    ))
+}
+
+/// Moves or renames the file or directory at the percent-encoded `from` to `to`, both paths
+/// in the share. An existing destination is handled by `conflict_policy` like an upload, but
+/// directories are never replaced. Moves across filesystems fall back to copying and deleting.
+/// Responds with the moved entry, with `201` when it was created and `200` when it replaced
+/// an existing file.
+pub fn move_path(
+    share: &Share,
+    from: &str,
+    to: &str,
+    conflict_policy: ConflictPolicy,
+    config: &UploadConfig,
+) -> Result<HttpResponse, ApiError> {
+    let transfer = Transfer::prepare(share, from, to, conflict_policy, config, true)?;
+    transfer.complete(move_entry(
+        &transfer.source.path,
+        &transfer.target_path,
+        &transfer.target_dir.staging_dir(),
+    ))
+}
+
+/// Copies the file or directory at the percent-encoded `from` to `to`, recursing into
+/// directories. Conflicts are handled as for `move_path`, and the response is the same.
+pub fn copy_path(
+    share: &Share,
+    from: &str,
+    to: &str,
+    conflict_policy: ConflictPolicy,
+    config: &UploadConfig,
+) -> Result<HttpResponse, ApiError> {
+    let transfer = Transfer::prepare(share, from, to, conflict_policy, config, false)?;
+    transfer.complete(copy_into_place(
+        &transfer.source.path,
+        &transfer.target_path,
+        &transfer.target_dir.staging_dir(),
+    ))
+}
+
+/// A checked move or copy of `source` to `target_path`, a location in `target_dir`.
+struct Transfer {
+    source: ResolvedPath,
+    target_dir: ResolvedPath,
+    target_path: PathBuf,
+    replaced: bool,
+    claimed: bool,
+}
+
+impl Transfer {
+    /// Resolves both paths and applies the conflict policy, file type filters and quotas to
+    /// the destination, then claims its name there. Only moves need `from` to be writable.
+    fn prepare(
+        share: &Share,
+        from: &str,
+        to: &str,
+        conflict_policy: ConflictPolicy,
+        config: &UploadConfig,
+        moved: bool,
+    ) -> Result<Self, ApiError> {
+        let source = share.resolve_entry(from)?;
+        let verb = if moved { "moved" } else { "copied" };
+        if source.path == source.base {
+            return Err(ApiError::new_with_json(
+                400,
+                &format!("The served directory cannot be {}", verb),
+            ));
+        }
+        if moved {
+            source.check_writable()?;
+        }
+        let (target_dir, target_path) = destination(share, to)?;
+        if target_path.starts_with(&source.path) {
+            return Err(ApiError::new_with_json(
+                400,
+                &format!("A path cannot be {} into itself", verb),
+            ));
+        }
+
+        let existing = fs::symlink_metadata(&target_path).ok();
+        if existing.as_ref().is_some_and(|existing| existing.is_dir())
+            && conflict_policy != ConflictPolicy::Rename
+        {
+            return Err(ApiError::new_with_json(
+                400,
+                "Path points to an existing directory",
+            ));
+        }
+        if existing.is_some() && conflict_policy == ConflictPolicy::Reject {
+            return Err(ApiError::new_with_json(409, "File already exists"));
+        }
+        let replaced = existing.is_some() && conflict_policy == ConflictPolicy::Overwrite;
+
+        //the new name must not make a file one the upload filters would have rejected
+        let source_is_dir = fs::symlink_metadata(&source.path)?.is_dir();
+        if !source_is_dir {
+            let mut head = Vec::new();
+            File::open(&source.path)?
+                .take(SNIFF_LENGTH)
+                .read_to_end(&mut head)?;
+            let file_name = target_path.file_name().unwrap_or_default();
+            config
+                .file_types
+                .check(&file_name.to_string_lossy(), &head)
+                .map_err(|err| ApiError::new_with_json(415, &err))?;
+        }
+        check_quota(&source, &target_dir, &target_path, config, moved)?;
+
+        if let Some(parent) = target_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        if replaced && source_is_dir {
+            fs::remove_file(&target_path)?;
+        }
+        //claimed last, so a name taken by another request since the checks above is a conflict
+        let Some(target_path) = claim_path(&target_path, conflict_policy, source_is_dir)? else {
+            return Err(ApiError::new_with_json(409, "File already exists"));
+        };
+        Ok(Transfer {
+            source,
+            target_dir,
+            target_path,
+            replaced,
+            claimed: conflict_policy != ConflictPolicy::Overwrite,
+        })
+    }
+
+    /// Responds with the entry once it was moved or copied by `result`, otherwise gives up
+    /// the claimed name.
+    fn complete(&self, result: io::Result<()>) -> Result<HttpResponse, ApiError> {
+        if let Err(err) = result {
+            if self.claimed {
+                release_claim(&self.target_path);
+            }
+            return Err(err.into());
+        }
+        Ok(HttpResponse::new(
+            Some(Body::Json(file_info(&self.target_dir, &self.target_path)?)),
+            None,
+            if self.replaced { 200 } else { 201 },
+        ))
+    }
+}
+
+/// Resolves the percent-encoded `path` of an entry about to be created, sanitized like the
+/// name of an uploaded file, returning the writable directory it is created in with its path.
+fn destination(share: &Share, path: &str) -> Result<(ResolvedPath, PathBuf), ApiError> {
+    let name = percent_encoding::percent_decode_str(path).decode_utf8_lossy();
+    let relative_path =
+        relative_upload_path(&name).map_err(|err| ApiError::new_with_json(400, &err))?;
+    let (dir, relative_path) = share.locate_new(&relative_path)?;
+    dir.check_writable()?;
+    let path = dir.resolve_new(&relative_path)?;
+    Ok((dir, path))
+}
+
+/// Fails with `507` when `source` is brought into a top-level directory whose quota it does
+/// not fit. A `moved` entry staying within the same quota directory does not change its size.
+fn check_quota(
+    source: &ResolvedPath,
+    target_dir: &ResolvedPath,
+    target_path: &Path,
+    config: &UploadConfig,
+    moved: bool,
+) -> Result<(), ApiError> {
+    let Some(quota) = directory_quota(target_dir, target_path, config)? else {
+        return Ok(());
+    };
+    if moved
+        && directory_quota(source, &source.path, config)?
+            .is_some_and(|source_quota| source_quota.directory == quota.directory)
+    {
+        return Ok(());
+    }
+    let metadata = fs::symlink_metadata(&source.path)?;
+    let size = match metadata.is_dir() {
+        true => directory_size(&source.path)?,
+        false => metadata.len(),
+    };
+    if size > quota.remaining {
+        return Err(ApiError::new_with_json(
+            507,
+            &format!(
+                "Path does not fit the quota of directory {}, {} of {} bytes left",
+                quota.directory, quota.remaining, quota.limit
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// Renames `source` to `target`, copying and deleting it when they are on different
+/// filesystems. The copy is assembled in `staging_dir`, on the filesystem of `target`.
+pub fn move_entry(source: &Path, target: &Path, staging_dir: &Path) -> io::Result<()> {
+    match fs::rename(source, target) {
+        Err(err) if err.kind() == io::ErrorKind::CrossesDevices => {
+            copy_into_place(source, target, staging_dir)?;
+            remove_entry(source, &mut Vec::new())
+        }
+        result => result,
+    }
+}
+
+/// Copies `source` into `staging_dir` under a temporary name and renames it into place, so a
+/// copy in progress never shows up in the share, a failed copy leaves nothing behind and an
+/// existing file is only replaced by a full copy.
+fn copy_into_place(source: &Path, target: &Path, staging_dir: &Path) -> io::Result<()> {
+    fs::create_dir_all(staging_dir)?;
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    let temp_path = staging_dir.join(format!(
+        "{}.{:x}.copy",
+        target.file_name().unwrap_or_default().to_string_lossy(),
+        nanos
+    ));
+    let copied = copy_entry(source, &temp_path).and_then(|_| fs::rename(&temp_path, target));
+    if copied.is_err() {
+        let _ = remove_entry(&temp_path, &mut Vec::new());
+    }
+    copied
+}
+
+/// Copies `source` to `target`, recursing into directories. Symlinks are recreated rather
+/// than followed, so a copy never pulls in content from outside the share.
+fn copy_entry(source: &Path, target: &Path) -> io::Result<()> {
+    let metadata = fs::symlink_metadata(source)?;
+    if metadata.is_dir() {
+        fs::create_dir(target)?;
+        for entry in fs::read_dir(source)? {
+            let entry = entry?;
+            copy_entry(&entry.path(), &target.join(entry.file_name()))?;
+        }
+        Ok(())
+    } else if metadata.is_symlink() {
+        copy_symlink(source, target)
+    } else {
+        fs::copy(source, target).map(|_| ())
+    }
+}
+
+#[cfg(unix)]
+fn copy_symlink(source: &Path, target: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(fs::read_link(source)?, target)
+}
+
+#[cfg(windows)]
+fn copy_symlink(source: &Path, target: &Path) -> io::Result<()> {
+    match source.is_dir() {
+        true => std::os::windows::fs::symlink_dir(fs::read_link(source)?, target),
+        false => std::os::windows::fs::symlink_file(fs::read_link(source)?, target),
+    }
+}
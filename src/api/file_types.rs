@@ -0,0 +1,148 @@
+use std::path::Path;
+
+/// Number of leading bytes of an upload inspected to detect its actual type.
+pub const SNIFF_LENGTH: u64 = 16;
+
+/// File signatures recognized by `sniff_mime`. Containers such as zip are left out on purpose,
+/// as documents built on them (docx, jar, epub...) would otherwise be reported as archives.
+const SIGNATURES: [(&[u8], &str); 14] = [
+    (b"MZ", "application/x-msdownload"),
+    (b"\x7fELF", "application/x-executable"),
+    (b"\xfe\xed\xfa\xce", "application/x-mach-binary"),
+    (b"\xfe\xed\xfa\xcf", "application/x-mach-binary"),
+    (b"\xce\xfa\xed\xfe", "application/x-mach-binary"),
+    (b"\xcf\xfa\xed\xfe", "application/x-mach-binary"),
+    (b"#!", "application/x-sh"),
+    (b"\x89PNG\r\n\x1a\n", "image/png"),
+    (b"\xff\xd8\xff", "image/jpeg"),
+    (b"GIF87a", "image/gif"),
+    (b"GIF89a", "image/gif"),
+    (b"%PDF-", "application/pdf"),
+    (b"\x1f\x8b", "application/gzip"),
+    (b"7z\xbc\xaf\x27\x1c", "application/x-7z-compressed"),
+];
+
+/// Detects the type of a file from its first bytes, as read up to `SNIFF_LENGTH`.
+pub fn sniff_mime(head: &[u8]) -> Option<&'static str> {
+    if head.len() >= 12 && &head[..4] == b"RIFF" && &head[8..12] == b"WEBP" {
+        return Some("image/webp");
+    }
+    SIGNATURES
+        .iter()
+        .find(|(signature, _)| head.starts_with(signature))
+        .map(|(_, mime)| *mime)
+}
+
+/// Extension and MIME type allow and deny lists applied to uploads. Empty allow lists allow
+/// everything. MIME types are checked both for the type guessed from the extension and the
+/// type sniffed from the content, so a renamed executable does not pass for an image.
+#[derive(Default)]
+pub struct FileTypeFilter {
+    pub allowed_extensions: Vec<String>,
+    pub denied_extensions: Vec<String>,
+    pub allowed_types: Vec<String>,
+    pub denied_types: Vec<String>,
+}
+
+impl FileTypeFilter {
+    fn is_empty(&self) -> bool {
+        self.allowed_extensions.is_empty()
+            && self.denied_extensions.is_empty()
+            && self.allowed_types.is_empty()
+            && self.denied_types.is_empty()
+    }
+
+    /// Checks a file `name` and the first bytes of its content, returning the reason it is
+    /// not accepted.
+    pub fn check(&self, name: &str, head: &[u8]) -> Result<(), String> {
+        if self.is_empty() {
+            return Ok(());
+        }
+        let extension = Path::new(name)
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        let extension_listed = |list: &[String]| list.contains(&extension);
+        if (!self.allowed_extensions.is_empty() && !extension_listed(&self.allowed_extensions))
+            || extension_listed(&self.denied_extensions)
+        {
+            return Err(match extension.is_empty() {
+                true => String::from("Files without an extension are not allowed"),
+                false => format!("Files with the extension .{} are not allowed", extension),
+            });
+        }
+
+        let mut types: Vec<String> = mime_guess::from_path(name)
+            .first()
+            .map(|mime| mime.essence_str().to_string())
+            .into_iter()
+            .collect();
+        if let Some(sniffed) =
+            sniff_mime(head).filter(|sniffed| !types.iter().any(|t| t == sniffed))
+        {
+            types.push(sniffed.to_string());
+        }
+        let not_allowed = |mime: &str| format!("Files of type {} are not allowed", mime);
+        if let Some(denied) = types
+            .iter()
+            .find(|mime| mime_listed(&self.denied_types, mime))
+        {
+            return Err(not_allowed(denied));
+        }
+        if !self.allowed_types.is_empty() {
+            if types.is_empty() {
+                return Err(String::from("The file type could not be determined"));
+            }
+            if let Some(denied) = types
+                .iter()
+                .find(|mime| !mime_listed(&self.allowed_types, mime))
+            {
+                return Err(not_allowed(denied));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Matches `mime` against patterns like `image/png`, `image/*` or `*/*`.
+fn mime_listed(patterns: &[String], mime: &str) -> bool {
+    patterns
+        .iter()
+        .any(|pattern| match pattern.split_once('/') {
+            Some(("*", "*")) => true,
+            Some((top_level, "*")) => mime
+                .split_once('/')
+                .is_some_and(|(mime_top_level, _)| mime_top_level == top_level),
+            _ => pattern == mime,
+        })
+}
+
+/// Parses a comma separated list of file extensions, with or without the leading dot.
+pub fn parse_extensions(value: &str) -> Result<Vec<String>, String> {
+    value
+        .split(',')
+        .map(|extension| extension.trim().trim_start_matches('.').to_lowercase())
+        .map(
+            |extension| match extension.is_empty() || extension.contains(['/', '\\']) {
+                true => Err(format!("Invalid file extension in '{}'", value)),
+                false => Ok(extension),
+            },
+        )
+        .collect()
+}
+
+/// Parses a comma separated list of MIME types, which may use `*` as subtype.
+pub fn parse_mime_types(value: &str) -> Result<Vec<String>, String> {
+    value
+        .split(',')
+        .map(|mime| mime.trim().to_lowercase())
+        .map(|mime| match mime.split_once('/') {
+            Some((top_level, subtype))
+                if !top_level.is_empty() && !subtype.is_empty() && !subtype.contains('/') =>
+            {
+                Ok(mime)
+            }
+            _ => Err(format!("Invalid MIME type in '{}'", value)),
+        })
+        .collect()
+}
@@ -2,21 +2,32 @@ use clap::builder::{
     styling::{AnsiColor, Effects},
     Styles,
 };
+use file_ops::{copy_path, create_directory, delete_path, move_path};
+use file_types::{parse_extensions, parse_mime_types, FileTypeFilter};
 use mime_guess::mime;
 use scratch_server::{
     api_error::ApiError, Body, Cors, HttpMethod, HttpResponse, HttpServer, Router, STATIC_FILES,
 };
-use std::{fs::File, path::PathBuf, sync::Arc};
-use utils::parse_index_path;
+use std::{fs::File, io, path::PathBuf, sync::Arc};
+use utils::{
+    header_sha256, parse_conflict_policy, parse_index_path, parse_mount, parse_quota, parse_root,
+    parse_size, query_flag, requested_conflict_policy, upload_files, upload_raw, ConflictPolicy,
+    Mount, Share, UploadConfig,
+};
 
-use self::utils::list_directory;
+use self::{tus::add_tus_routes, utils::list_directory};
 
+mod file_ops;
+mod file_types;
+mod tus;
 mod utils;
 
 pub struct ServerConfig {
     pub server: HttpServer,
     pub authorize: bool,
     pub index_path: Option<PathBuf>,
+    pub upload_config: UploadConfig,
+    pub mounts: Vec<Mount>,
 }
 
 pub fn build_server() -> ServerConfig {
@@ -87,6 +98,58 @@ pub fn build_server() -> ServerConfig {
                 .required(false)
                 .value_parser(parse_index_path)
                 .help("Sets the path to custom index html file to serve"))
+            .arg(clap::Arg::new("root")
+                .long("root")
+                .value_name("dir")
+                .value_parser(parse_root)
+                .help("Directory to serve, defaults to the current directory"))
+            .arg(clap::Arg::new("mount")
+                .long("mount")
+                .value_name("name=/path[:ro]")
+                .action(clap::ArgAction::Append)
+                .conflicts_with("root")
+                .value_parser(parse_mount)
+                .help("Share a directory as a top-level folder, can be repeated"))
+            .arg(clap::Arg::new("on-conflict")
+                .long("on-conflict")
+                .default_value("overwrite")
+                .value_parser(parse_conflict_policy)
+                .help("Handling of existing files on upload: reject, overwrite or rename"))
+            .arg(clap::Arg::new("max-upload-size")
+                .long("max-upload-size")
+                .value_name("size")
+                .value_parser(parse_size)
+                .help("Maximum size of an uploaded file, e.g. 500M"))
+            .arg(clap::Arg::new("quota")
+                .long("quota")
+                .value_name("dir=size")
+                .action(clap::ArgAction::Append)
+                .value_parser(parse_quota)
+                .help("Size limit of a top-level directory, can be repeated"))
+            .arg(clap::Arg::new("allow-ext")
+                .long("allow-ext")
+                .value_name("ext,...")
+                .action(clap::ArgAction::Append)
+                .value_parser(parse_extensions)
+                .help("Only accept uploads with these file extensions"))
+            .arg(clap::Arg::new("deny-ext")
+                .long("deny-ext")
+                .value_name("ext,...")
+                .action(clap::ArgAction::Append)
+                .value_parser(parse_extensions)
+                .help("Reject uploads with these file extensions"))
+            .arg(clap::Arg::new("allow-mime")
+                .long("allow-mime")
+                .value_name("type,...")
+                .action(clap::ArgAction::Append)
+                .value_parser(parse_mime_types)
+                .help("Only accept uploads of these MIME types, e.g. image/*"))
+            .arg(clap::Arg::new("deny-mime")
+                .long("deny-mime")
+                .value_name("type,...")
+                .action(clap::ArgAction::Append)
+                .value_parser(parse_mime_types)
+                .help("Reject uploads of these MIME types"))
             .get_matches();
 
     let mut server = HttpServer::build(
@@ -117,17 +180,50 @@ pub fn build_server() -> ServerConfig {
                 .with_credentials("true"),
         );
     }
+    if let Some(root) = args.remove_one::<PathBuf>("root") {
+        server = server.with_root(root);
+    }
+
     let index_path = args.remove_one::<PathBuf>("index");
+    let upload_config = UploadConfig {
+        conflict_policy: args.remove_one::<ConflictPolicy>("on-conflict").unwrap(),
+        max_size: args.remove_one::<u64>("max-upload-size"),
+        quotas: args
+            .remove_many::<(String, u64)>("quota")
+            .map(|quotas| quotas.collect())
+            .unwrap_or_default(),
+        file_types: FileTypeFilter {
+            allowed_extensions: list_arg(&mut args, "allow-ext"),
+            denied_extensions: list_arg(&mut args, "deny-ext"),
+            allowed_types: list_arg(&mut args, "allow-mime"),
+            denied_types: list_arg(&mut args, "deny-mime"),
+        },
+    };
+    let mounts = args
+        .remove_many::<Mount>("mount")
+        .map(|mounts| mounts.collect())
+        .unwrap_or_default();
     ServerConfig {
         server,
         authorize: auth,
         index_path,
+        upload_config,
+        mounts,
     }
 }
 
+/// Collects a repeatable argument holding comma separated lists.
+fn list_arg(args: &mut clap::ArgMatches, id: &str) -> Vec<String> {
+    args.remove_many::<Vec<String>>(id)
+        .map(|lists| lists.flatten().collect())
+        .unwrap_or_default()
+}
+
 pub fn create_routes(
     authorize: bool,
     index_path: Option<PathBuf>,
+    upload_config: UploadConfig,
+    mounts: Vec<Mount>,
 ) -> Box<dyn Fn(&mut Router) + Send + Sync> {
     if let Some(path) = index_path {
         let path_arc = Arc::new(path);
@@ -224,6 +320,8 @@ pub fn create_routes(
         };
         return Box::new(closure);
     }
+    let upload_config = Arc::new(upload_config);
+    let mounts = Arc::new(mounts);
     let closure = move |router: &mut Router| {
         router.add_route(
             "/static/{file}?",
@@ -256,11 +354,17 @@ pub fn create_routes(
             },
             authorize,
         );
-        router.add_route(
+        let files_mounts = Arc::clone(&mounts);
+        router.add_handler(
             "/api/files",
             HttpMethod::GET,
-            |_, params| {
-                let file_path = PathBuf::from(params.get("path").ok_or("Missing path parameter")?);
+            move |request| {
+                let file_path = Share::new(request.root(), &files_mounts)
+                    .resolve(request.query("path").ok_or("Missing path parameter")?)?
+                    .path;
+                if !file_path.is_file() {
+                    return Err(ApiError::new_with_json(400, "Path is not a file"));
+                }
                 let file_name = file_path
                     .file_name()
                     .ok_or("No file name")?
@@ -281,13 +385,141 @@ pub fn create_routes(
             authorize,
         );
 
-        router.add_route(
+        let put_config = Arc::clone(&upload_config);
+        let put_mounts = Arc::clone(&mounts);
+        router.add_handler(
+            "/api/files",
+            HttpMethod::PUT,
+            move |mut request| {
+                let path = request.query("path").ok_or("Missing path parameter")?;
+                let conflict_policy =
+                    requested_conflict_policy(&request, put_config.conflict_policy)?;
+                let expected_sha256 =
+                    header_sha256(request.header("Content-Digest"), request.header("Digest"))?;
+                let expected_sha256 = expected_sha256.as_deref();
+                let share = Share::new(request.root(), &put_mounts);
+                match request.body() {
+                    Some(body) => upload_raw(
+                        body,
+                        &share,
+                        path,
+                        expected_sha256,
+                        conflict_policy,
+                        &put_config,
+                    ),
+                    None => upload_raw(
+                        &mut io::empty(),
+                        &share,
+                        path,
+                        expected_sha256,
+                        conflict_policy,
+                        &put_config,
+                    ),
+                }
+            },
+            authorize,
+        );
+
+        let delete_mounts = Arc::clone(&mounts);
+        router.add_handler(
+            "/api/files",
+            HttpMethod::DELETE,
+            move |request| {
+                delete_path(
+                    &Share::new(request.root(), &delete_mounts),
+                    request.query("path").ok_or("Missing path parameter")?,
+                    query_flag(&request, "recursive")?,
+                )
+            },
+            authorize,
+        );
+
+        let move_config = Arc::clone(&upload_config);
+        let move_mounts = Arc::clone(&mounts);
+        router.add_handler(
+            "/api/files/move",
+            HttpMethod::POST,
+            move |request| {
+                let conflict_policy =
+                    requested_conflict_policy(&request, move_config.conflict_policy)?;
+                move_path(
+                    &Share::new(request.root(), &move_mounts),
+                    request.query("from").ok_or("Missing from parameter")?,
+                    request.query("to").ok_or("Missing to parameter")?,
+                    conflict_policy,
+                    &move_config,
+                )
+            },
+            authorize,
+        );
+
+        let copy_config = Arc::clone(&upload_config);
+        let copy_mounts = Arc::clone(&mounts);
+        router.add_handler(
+            "/api/files/copy",
+            HttpMethod::POST,
+            move |request| {
+                let conflict_policy =
+                    requested_conflict_policy(&request, copy_config.conflict_policy)?;
+                copy_path(
+                    &Share::new(request.root(), &copy_mounts),
+                    request.query("from").ok_or("Missing from parameter")?,
+                    request.query("to").ok_or("Missing to parameter")?,
+                    conflict_policy,
+                    &copy_config,
+                )
+            },
+            authorize,
+        );
+
+        let upload_files_config = Arc::clone(&upload_config);
+        let upload_files_mounts = Arc::clone(&mounts);
+        router.add_handler(
+            "/api/upload",
+            HttpMethod::POST,
+            move |mut request| {
+                let content_type = request
+                    .header("Content-Type")
+                    .filter(|content_type| content_type.contains("multipart/form-data"))
+                    .ok_or("Expected multipart/form-data request")?;
+                //the Path header is still accepted for clients that predate the path parameter
+                let path = request
+                    .query("path")
+                    .or_else(|| request.header("Path"))
+                    .ok_or("Missing path parameter")?;
+                let conflict_policy =
+                    requested_conflict_policy(&request, upload_files_config.conflict_policy)?;
+                let share = Share::new(request.root(), &upload_files_mounts);
+                let body = request.body().ok_or("Missing request body")?;
+
+                upload_files(
+                    content_type,
+                    body,
+                    &share,
+                    path,
+                    conflict_policy,
+                    &upload_files_config,
+                )
+            },
+            authorize,
+        );
+
+        add_tus_routes(
+            router,
+            authorize,
+            Arc::clone(&upload_config),
+            Arc::clone(&mounts),
+        );
+
+        let directory_mounts = Arc::clone(&mounts);
+        router.add_handler(
             "/api/directory",
             HttpMethod::GET,
-            |_, params| {
+            move |request| {
                 Ok(HttpResponse::new(
                     Some(Body::Json(list_directory(
-                        params.get("path").ok_or("Missing path parameter")?,
+                        &Share::new(request.root(), &directory_mounts),
+                        request.query("path").ok_or("Missing path parameter")?,
                     )?)),
                     None,
                     200,
@@ -296,6 +528,20 @@ pub fn create_routes(
             authorize,
         );
 
+        let create_directory_mounts = Arc::clone(&mounts);
+        router.add_handler(
+            "/api/directory",
+            HttpMethod::POST,
+            move |request| {
+                create_directory(
+                    &Share::new(request.root(), &create_directory_mounts),
+                    request.query("path").ok_or("Missing path parameter")?,
+                    query_flag(&request, "parents")?,
+                )
+            },
+            authorize,
+        );
+
         router.add_route(
             "/*",
             HttpMethod::GET,
@@ -0,0 +1,433 @@
+use std::{
+    collections::{hash_map::RandomState, HashMap, HashSet},
+    fs::{self, File, OpenOptions},
+    hash::BuildHasher,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+
+use base64::{prelude::BASE64_STANDARD, Engine};
+use scratch_server::{api_error::ApiError, HttpMethod, HttpResponse, Request, Router};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    file_ops::move_entry,
+    file_types::SNIFF_LENGTH,
+    utils::{
+        check_max_size, claim_path, directory_quota, file_sha256, parse_hex_sha256,
+        relative_upload_path, release_claim, requested_conflict_policy, to_hex, upload_directory,
+        ConflictPolicy, Mount, ResolvedPath, Share, UploadConfig,
+    },
+};
+
+const TUS_VERSION: &str = "1.0.0";
+const TUS_EXTENSIONS: &str = "creation,termination";
+
+/// State of a resumable upload, stored next to its data in the staging directory. The
+/// current offset is the size of the data file.
+#[derive(Serialize, Deserialize)]
+struct TusUpload {
+    length: u64,
+    /// Upload directory and file name as sent by the client. They are resolved in the share
+    /// again when the upload is finished, so it is only stored where new files are allowed.
+    path: String,
+    name: String,
+    conflict_policy: ConflictPolicy,
+    /// Hex SHA-256 digest the complete upload must match.
+    sha256: Option<String>,
+}
+
+impl TusUpload {
+    fn relative_path(&self) -> Result<PathBuf, ApiError> {
+        relative_upload_path(&self.name).map_err(|err| ApiError::new_with_json(400, &err))
+    }
+
+    /// Directory the upload is stored in and its path there.
+    fn target(&self, share: &Share) -> Result<(ResolvedPath, PathBuf), ApiError> {
+        let relative_path = self.relative_path()?;
+        let target_dir = upload_directory(share, &self.path)?;
+        let target = target_dir.resolve_new(&relative_path)?;
+        Ok((target_dir, target))
+    }
+}
+
+/// Registers the tus 1.0 resumable upload protocol under `/api/tus`, with the creation and
+/// termination extensions.
+pub fn add_tus_routes(
+    router: &mut Router,
+    authorize: bool,
+    config: Arc<UploadConfig>,
+    mounts: Arc<Vec<Mount>>,
+) {
+    let locks = Arc::new(Mutex::new(HashSet::new()));
+
+    router.add_handler(
+        "/api/tus",
+        HttpMethod::OPTIONS,
+        |_| {
+            Ok(HttpResponse::new(None, None, 204)
+                .add_response_header("Tus-Resumable", TUS_VERSION)
+                .add_response_header("Tus-Version", TUS_VERSION)
+                .add_response_header("Tus-Extension", TUS_EXTENSIONS))
+        },
+        false,
+    );
+    router.add_handler(
+        "/api/tus",
+        HttpMethod::POST,
+        {
+            let config = Arc::clone(&config);
+            let mounts = Arc::clone(&mounts);
+            move |request| tus_response(create_upload(&request, &config, &mounts))
+        },
+        authorize,
+    );
+    router.add_handler(
+        "/api/tus/{id}",
+        HttpMethod::HEAD,
+        {
+            let mounts = Arc::clone(&mounts);
+            move |request| tus_response(upload_offset(&request, &mounts))
+        },
+        authorize,
+    );
+    router.add_handler(
+        "/api/tus/{id}",
+        HttpMethod::PATCH,
+        {
+            let mounts = Arc::clone(&mounts);
+            let locks = Arc::clone(&locks);
+            move |mut request| tus_response(append_upload(&mut request, &locks, &config, &mounts))
+        },
+        authorize,
+    );
+    router.add_handler(
+        "/api/tus/{id}",
+        HttpMethod::DELETE,
+        move |request| tus_response(terminate_upload(&request, &locks, &mounts)),
+        authorize,
+    );
+}
+
+/// Every tus response carries the protocol version, errors included.
+fn tus_response(result: Result<HttpResponse, ApiError>) -> Result<HttpResponse, ApiError> {
+    Ok(result
+        .unwrap_or_else(ApiError::into_response)
+        .add_response_header("Tus-Resumable", TUS_VERSION))
+}
+
+fn check_version(request: &Request) -> Result<(), ApiError> {
+    if request.header("Tus-Resumable") != Some(TUS_VERSION) {
+        return Err(ApiError::new_with_custom(
+            ApiError::new_with_json(412, "Unsupported tus protocol version")
+                .into_response()
+                .add_response_header("Tus-Version", TUS_VERSION),
+        ));
+    }
+    Ok(())
+}
+
+fn create_upload(
+    request: &Request,
+    config: &UploadConfig,
+    mounts: &[Mount],
+) -> Result<HttpResponse, ApiError> {
+    check_version(request)?;
+    if request.header("Upload-Defer-Length").is_some() {
+        return Err(ApiError::new_with_json(
+            400,
+            "Deferred upload length is not supported",
+        ));
+    }
+    let length = request
+        .header("Upload-Length")
+        .and_then(|length| length.parse::<u64>().ok())
+        .ok_or("Missing or invalid Upload-Length header")?;
+    check_max_size(length, config)?;
+
+    let metadata = parse_metadata(request.header("Upload-Metadata").unwrap_or_default())?;
+    let name = metadata
+        .get("filename")
+        .or_else(|| metadata.get("name"))
+        .ok_or("Missing filename in Upload-Metadata header")?;
+    let upload = TusUpload {
+        length,
+        path: request.query("path").unwrap_or_default().to_string(),
+        name: name.to_string(),
+        conflict_policy: requested_conflict_policy(request, config.conflict_policy)?,
+        sha256: match metadata.get("sha256") {
+            Some(digest) => Some(to_hex(&parse_hex_sha256(digest)?)),
+            None => None,
+        },
+    };
+    //the content is checked as well once its first bytes arrive
+    config
+        .file_types
+        .check(&upload.relative_path()?.to_string_lossy(), &[])
+        .map_err(|err: String| ApiError::new_with_json(415, &err))?;
+    let share = Share::new(request.root(), mounts);
+    let (target_dir, target) = upload.target(&share)?;
+
+    if upload.conflict_policy == ConflictPolicy::Reject && target.exists() {
+        return Err(ApiError::new_with_json(409, "File already exists"));
+    }
+    if let Some(quota) = directory_quota(&target_dir, &target, config)? {
+        if length > quota.remaining {
+            return Err(ApiError::new_with_json(
+                507,
+                &format!(
+                    "Upload does not fit the quota of directory {}, {} of {} bytes left",
+                    quota.directory, quota.remaining, quota.limit
+                ),
+            ));
+        }
+    }
+
+    let staging_dir = target_dir.staging_dir();
+    let id = new_upload_id();
+    fs::create_dir_all(&staging_dir)?;
+    File::create_new(data_path(&staging_dir, &id))?;
+    fs::write(info_path(&staging_dir, &id), serde_json::to_vec(&upload)?)?;
+
+    let response = HttpResponse::new(None, None, 201)
+        .add_response_header("Location", &format!("/api/tus/{}", id))
+        .add_response_header("Upload-Offset", "0");
+    match length {
+        0 => {
+            let sha256 = finish_upload(&share, &staging_dir, &id, &upload)?;
+            Ok(response.add_response_header("Upload-Sha256", &sha256))
+        }
+        _ => Ok(response),
+    }
+}
+
+fn upload_offset(request: &Request, mounts: &[Mount]) -> Result<HttpResponse, ApiError> {
+    check_version(request)?;
+    let share = Share::new(request.root(), mounts);
+    let id = request.param("id").unwrap_or_default();
+    let (staging_dir, upload) = load_upload(&share, id)?;
+    let offset = fs::metadata(data_path(&staging_dir, id))?.len();
+
+    Ok(HttpResponse::new(None, None, 200)
+        .add_response_header("Upload-Offset", &offset.to_string())
+        .add_response_header("Upload-Length", &upload.length.to_string())
+        .add_response_header("Cache-Control", "no-store"))
+}
+
+/// Appends the request body at `Upload-Offset`. Whatever arrives before the connection
+/// drops is kept, so the client can resume from the new offset. The first bytes of an upload
+/// are checked against the file type filter before they are stored, a rejected upload is
+/// terminated.
+fn append_upload(
+    request: &mut Request,
+    locks: &Mutex<HashSet<String>>,
+    config: &UploadConfig,
+    mounts: &[Mount],
+) -> Result<HttpResponse, ApiError> {
+    check_version(request)?;
+    if request.header("Content-Type") != Some("application/offset+octet-stream") {
+        return Err(ApiError::new_with_json(
+            415,
+            "Content-Type must be application/offset+octet-stream",
+        ));
+    }
+    let offset = request
+        .header("Upload-Offset")
+        .and_then(|offset| offset.parse::<u64>().ok())
+        .ok_or("Missing or invalid Upload-Offset header")?;
+    let share = Share::new(request.root(), mounts);
+    let id = request.param("id").unwrap_or_default();
+    let _lock = UploadLock::acquire(locks, id)?;
+    let (staging_dir, upload) = load_upload(&share, id)?;
+
+    let data_path = data_path(&staging_dir, id);
+    let current_offset = fs::metadata(&data_path)?.len();
+    if offset != current_offset {
+        return Err(ApiError::new_with_json(
+            409,
+            "Upload-Offset does not match the current offset",
+        ));
+    }
+
+    let mut body = match request.body() {
+        Some(body) => Some(body.take(upload.length.saturating_sub(current_offset))),
+        None => None,
+    };
+    let mut head = Vec::new();
+    let mut head_read = Ok(0);
+    if current_offset == 0 {
+        //a body cut short still leaves the bytes received so far in `head`, stored below
+        if let Some(body) = body.as_mut() {
+            head_read = body.by_ref().take(SNIFF_LENGTH).read_to_end(&mut head);
+        }
+        let relative_path = upload.relative_path()?;
+        let name = relative_path.file_name().unwrap_or_default();
+        if let Err(err) = config.file_types.check(&name.to_string_lossy(), &head) {
+            remove_upload(&staging_dir, id)?;
+            return Err(ApiError::new_with_json(415, &err));
+        }
+    }
+
+    let mut file = OpenOptions::new().append(true).open(&data_path)?;
+    let copied = file
+        .write_all(&head)
+        .and(head_read)
+        .and_then(|_| match body.as_mut() {
+            Some(body) => io::copy(body, &mut file).map(|copied| copied + head.len() as u64),
+            None => Ok(head.len() as u64),
+        });
+    file.sync_data()?;
+    let new_offset = current_offset + copied?;
+
+    let response = HttpResponse::new(None, None, 204)
+        .add_response_header("Upload-Offset", &new_offset.to_string());
+    if new_offset == upload.length {
+        let sha256 = finish_upload(&share, &staging_dir, id, &upload)?;
+        return Ok(response.add_response_header("Upload-Sha256", &sha256));
+    }
+    Ok(response)
+}
+
+fn terminate_upload(
+    request: &Request,
+    locks: &Mutex<HashSet<String>>,
+    mounts: &[Mount],
+) -> Result<HttpResponse, ApiError> {
+    check_version(request)?;
+    let share = Share::new(request.root(), mounts);
+    let id = request.param("id").unwrap_or_default();
+    let _lock = UploadLock::acquire(locks, id)?;
+    let (staging_dir, _) = load_upload(&share, id)?;
+    remove_upload(&staging_dir, id)?;
+
+    Ok(HttpResponse::new(None, None, 204))
+}
+
+/// Moves a complete upload to its target, copying it when the target is on another filesystem,
+/// such as a mount, and returns its hex SHA-256 digest. An upload rejected by the conflict
+/// policy or not matching its expected digest is discarded, one that could not be moved is kept
+/// so the next `PATCH` can retry.
+fn finish_upload(
+    share: &Share,
+    staging_dir: &Path,
+    id: &str,
+    upload: &TusUpload,
+) -> Result<String, ApiError> {
+    let sha256 = file_sha256(&data_path(staging_dir, id))?;
+    if let Some(expected) = upload
+        .sha256
+        .as_ref()
+        .filter(|expected| **expected != sha256)
+    {
+        remove_upload(staging_dir, id)?;
+        return Err(ApiError::new_with_json(
+            422,
+            &format!("Expected SHA-256 {}, received {}", expected, sha256),
+        ));
+    }
+    let (target_dir, target) = upload.target(share)?;
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let Some(stored_path) = claim_path(&target, upload.conflict_policy, false)? else {
+        remove_upload(staging_dir, id)?;
+        return Err(ApiError::new_with_json(409, "File already exists"));
+    };
+    let moved = move_entry(
+        &data_path(staging_dir, id),
+        &stored_path,
+        &target_dir.staging_dir(),
+    );
+    if let Err(err) = moved {
+        if upload.conflict_policy != ConflictPolicy::Overwrite {
+            release_claim(&stored_path);
+        }
+        return Err(err.into());
+    }
+    fs::remove_file(info_path(staging_dir, id))?;
+    Ok(sha256)
+}
+
+/// Finds the upload `id` in the staging directories of the share, returning the one holding
+/// it with its state.
+fn load_upload(share: &Share, id: &str) -> Result<(PathBuf, TusUpload), ApiError> {
+    let not_found = || ApiError::new_with_json(404, "Upload not found");
+    if id.len() != 32 || !id.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(not_found());
+    }
+    for staging_dir in share.staging_dirs()? {
+        match fs::read(info_path(&staging_dir, id)) {
+            Ok(info) => return Ok((staging_dir, serde_json::from_slice(&info)?)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+            Err(err) => return Err(err.into()),
+        }
+    }
+    Err(not_found())
+}
+
+fn remove_upload(staging_dir: &Path, id: &str) -> Result<(), ApiError> {
+    fs::remove_file(data_path(staging_dir, id))?;
+    fs::remove_file(info_path(staging_dir, id))?;
+    Ok(())
+}
+
+fn data_path(staging_dir: &Path, id: &str) -> PathBuf {
+    staging_dir.join(format!("{}.bin", id))
+}
+
+fn info_path(staging_dir: &Path, id: &str) -> PathBuf {
+    staging_dir.join(format!("{}.json", id))
+}
+
+/// Unguessable upload id, built from randomly seeded hashes as the crate has no RNG dependency.
+fn new_upload_id() -> String {
+    let random = || RandomState::new().hash_one(SystemTime::now());
+    format!("{:016x}{:016x}", random(), random())
+}
+
+/// Parses `Upload-Metadata`, a comma separated list of keys with optional base64 values.
+fn parse_metadata(header: &str) -> Result<HashMap<String, String>, ApiError> {
+    let invalid = || ApiError::new_with_json(400, "Invalid Upload-Metadata header");
+    header
+        .split(',')
+        .map(str::trim)
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once(' ').unwrap_or((pair, ""));
+            let value = BASE64_STANDARD
+                .decode(value.trim())
+                .map_err(|_| invalid())?;
+            let value = String::from_utf8(value).map_err(|_| invalid())?;
+            Ok((key.to_string(), value))
+        })
+        .collect()
+}
+
+/// Keeps concurrent requests from writing to the same upload.
+struct UploadLock<'a> {
+    locks: &'a Mutex<HashSet<String>>,
+    id: String,
+}
+
+impl<'a> UploadLock<'a> {
+    fn acquire(locks: &'a Mutex<HashSet<String>>, id: &str) -> Result<Self, ApiError> {
+        if !locks.lock().unwrap().insert(id.to_string()) {
+            return Err(ApiError::new_with_json(
+                409,
+                "Upload is being modified by another request",
+            ));
+        }
+        Ok(UploadLock {
+            locks,
+            id: id.to_string(),
+        })
+    }
+}
+
+impl Drop for UploadLock<'_> {
+    fn drop(&mut self) {
+        self.locks.lock().unwrap().remove(&self.id);
+    }
+}
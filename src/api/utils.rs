@@ -1,11 +1,20 @@
 use std::{
-    fs,
+    collections::HashMap,
+    fs::{self, File},
+    io::{self, Read},
     path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{SystemTime, UNIX_EPOCH},
 };
 
+use base64::{prelude::BASE64_STANDARD, Engine};
 use chrono::{DateTime, Utc};
-use scratch_server::api_error::ApiError;
+use scratch_server::{api_error::ApiError, Body, HttpResponse, Multipart, Request};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use unicode_normalization::UnicodeNormalization;
+
+use super::file_types::{FileTypeFilter, SNIFF_LENGTH};
 
 const SUFFIX: [&str; 9] = ["B", "KB", "MB", "GB", "TB", "PB", "EB", "ZB", "YB"];
 const UNIT: f64 = 1000.0;
@@ -35,6 +44,7 @@ struct Files {
 struct DirectoryInfoResponse {
     paths: Vec<PathParts>,
     files: Vec<Files>,
+    read_only: bool,
 }
 
 fn human_bytes<T: Into<f64>>(bytes: T) -> String {
@@ -53,22 +63,250 @@ fn human_bytes<T: Into<f64>>(bytes: T) -> String {
     [&result, SUFFIX[base.floor() as usize]].join(" ")
 }
 
-pub fn list_directory(path: &str) -> Result<serde_json::Value, ApiError> {
-    let base_root_path = Path::new("./").canonicalize()?;
-    let target_path = PathBuf::from("./").join(
-        percent_encoding::percent_decode_str(path)
-            .decode_utf8_lossy()
-            .to_string(),
-    );
-    let cannonical_target_path = target_path.canonicalize()?;
+const OUTSIDE_ROOT: &str = "Only paths inside the served directory are allowed";
+/// Private directory in the served root and in every mount that holds uploads in progress.
+/// Paths through it are never resolved, so its contents cannot be listed, read or replaced.
+pub const STAGING_DIR: &str = ".tus-staging";
 
-    let current_dir = std::env::current_dir()?.canonicalize()?;
-    if !cannonical_target_path.starts_with(current_dir) {
-        return Err(ApiError::new_with_json(
-            400,
-            "Only paths relative to the current directory are allowed",
-        ));
+/// A directory shared under `name` with `--mount`, shown as a top-level folder.
+#[derive(Debug, Clone)]
+pub struct Mount {
+    pub name: String,
+    pub path: PathBuf,
+    pub read_only: bool,
+}
+
+/// Parses a `name=/path[:ro]` mount. Mounts are writable unless marked `ro`.
+pub fn parse_mount(value: &str) -> Result<Mount, String> {
+    let (name, path) = value
+        .split_once('=')
+        .ok_or_else(|| String::from("The format must be name=/path[:ro]"))?;
+    let name = name.trim();
+    if name.is_empty() || name == "." || name == ".." || name.contains(['/', '\\']) {
+        return Err(String::from("Mount names must be a single path segment"));
+    }
+    let (path, read_only) = match path.rsplit_once(':') {
+        Some((path, "ro")) => (path, true),
+        Some((path, "rw")) => (path, false),
+        _ => (path, false),
+    };
+    let path = Path::new(path)
+        .canonicalize()
+        .ok()
+        .filter(|path| path.is_dir())
+        .ok_or_else(|| format!("Mount directory {} not found", path))?;
+    Ok(Mount {
+        name: name.to_string(),
+        path,
+        read_only,
+    })
+}
+
+/// The directories the file endpoints expose: the served root, or when mounts are
+/// configured, a virtual root holding the mounts as its folders.
+pub struct Share<'a> {
+    root: &'a Path,
+    mounts: &'a [Mount],
+}
+
+/// A client path resolved to a location inside the share.
+pub struct ResolvedPath {
+    /// Canonical path of the served root or mount the location belongs to.
+    pub base: PathBuf,
+    /// Name of the mount, `None` when serving the root.
+    pub mount: Option<String>,
+    pub path: PathBuf,
+    pub read_only: bool,
+}
+
+impl<'a> Share<'a> {
+    pub fn new(root: &'a Path, mounts: &'a [Mount]) -> Self {
+        Share { root, mounts }
+    }
+
+    /// Resolves a percent-encoded `path` sent by a client to an existing file or directory.
+    /// Symlinks are followed and the result must stay inside the served root or mount, so
+    /// `..` traversal, absolute paths and links pointing elsewhere are rejected with `400`.
+    pub fn resolve(&self, path: &str) -> Result<ResolvedPath, ApiError> {
+        let path = percent_encoding::percent_decode_str(path).decode_utf8_lossy();
+        let (mut location, rest) = self.locate(&path)?;
+        location.path = location.base.join(rest).canonicalize().map_err(not_found)?;
+        if !location.path.starts_with(&location.base) {
+            return Err(ApiError::new_with_json(400, OUTSIDE_ROOT));
+        }
+        if is_staging_path(&location.base, &location.path) {
+            return Err(ApiError::new_with_json(404, "Path not found"));
+        }
+        Ok(location)
+    }
+
+    /// Like `resolve`, but a symlink in the last component is not followed, so deleting or
+    /// moving the entry acts on the link rather than on what it points to.
+    pub fn resolve_entry(&self, path: &str) -> Result<ResolvedPath, ApiError> {
+        let decoded_path = percent_encoding::percent_decode_str(path).decode_utf8_lossy();
+        let (mut location, rest) = self.locate(&decoded_path)?;
+        let rest = PathBuf::from(rest);
+        let (Some(parent), Some(name)) = (rest.parent(), rest.file_name()) else {
+            return self.resolve(path);
+        };
+        let parent = location
+            .base
+            .join(parent)
+            .canonicalize()
+            .map_err(not_found)?;
+        if !parent.starts_with(&location.base) {
+            return Err(ApiError::new_with_json(400, OUTSIDE_ROOT));
+        }
+        location.path = parent.join(name);
+        if is_staging_path(&location.base, &location.path) {
+            return Err(ApiError::new_with_json(404, "Path not found"));
+        }
+        fs::symlink_metadata(&location.path).map_err(not_found)?;
+        Ok(location)
+    }
+
+    /// Splits a new file's sanitized `relative_path` into the served root or mount it is
+    /// created in and the path below it.
+    pub fn locate_new(&self, relative_path: &Path) -> Result<(ResolvedPath, PathBuf), ApiError> {
+        let (location, rest) = self.locate(&relative_path.to_string_lossy())?;
+        if rest.is_empty() {
+            return Err(ApiError::new_with_json(400, "Path points to a mount"));
+        }
+        Ok((location, PathBuf::from(rest)))
+    }
+
+    /// Staging directories of the served root or of every writable mount.
+    pub fn staging_dirs(&self) -> io::Result<Vec<PathBuf>> {
+        if self.mounts.is_empty() {
+            return Ok(vec![self.root.canonicalize()?.join(STAGING_DIR)]);
+        }
+        Ok(self
+            .mounts
+            .iter()
+            .filter(|mount| !mount.read_only)
+            .map(|mount| mount.path.join(STAGING_DIR))
+            .collect())
+    }
+
+    /// Whether a decoded `path` names the virtual root listing the mounts.
+    fn is_virtual_root(&self, path: &str) -> bool {
+        !self.mounts.is_empty() && name_components(path).next().is_none()
+    }
+
+    /// Finds the served root or mount a decoded client path belongs to, returning it with the
+    /// remaining path.
+    fn locate(&self, path: &str) -> Result<(ResolvedPath, String), ApiError> {
+        if self.mounts.is_empty() {
+            let base = self.root.canonicalize()?;
+            let location = ResolvedPath {
+                path: base.clone(),
+                base,
+                mount: None,
+                read_only: false,
+            };
+            return Ok((location, path.to_string()));
+        }
+
+        let mut components = name_components(path);
+        let name = components
+            .next()
+            .ok_or_else(|| ApiError::new_with_json(400, "Path must start with a mount name"))?;
+        let mount = self
+            .mounts
+            .iter()
+            .find(|mount| mount.name == name)
+            .ok_or_else(|| ApiError::new_with_json(404, "Mount not found"))?;
+        let location = ResolvedPath {
+            base: mount.path.clone(),
+            mount: Some(mount.name.clone()),
+            path: mount.path.clone(),
+            read_only: mount.read_only,
+        };
+        Ok((location, components.collect::<Vec<_>>().join("/")))
     }
+}
+
+impl ResolvedPath {
+    /// Resolves where a new file `relative_path` under this directory is created, rejecting
+    /// it with `400` when an existing part of the path is a symlink leading outside the
+    /// served root or mount.
+    pub fn resolve_new(&self, relative_path: &Path) -> Result<PathBuf, ApiError> {
+        let path = self.path.join(relative_path);
+        if escapes_root(&self.base, &path)? {
+            return Err(ApiError::new_with_json(400, OUTSIDE_ROOT));
+        }
+        if is_staging_path(&self.base, &path) {
+            return Err(ApiError::new_with_json(
+                400,
+                &format!("{} is reserved for uploads in progress", STAGING_DIR),
+            ));
+        }
+        Ok(path)
+    }
+
+    /// Staging directory of the served root or mount this location belongs to.
+    pub fn staging_dir(&self) -> PathBuf {
+        self.base.join(STAGING_DIR)
+    }
+
+    /// Fails with `403` when the location belongs to a read-only mount.
+    pub fn check_writable(&self) -> Result<(), ApiError> {
+        if self.read_only {
+            return Err(ApiError::new_with_json(403, "Mount is read-only"));
+        }
+        Ok(())
+    }
+
+    /// Path of `path`, a location below `base`, as seen by clients.
+    pub fn client_path(&self, path: &Path) -> String {
+        let relative = path.strip_prefix(&self.base).unwrap_or(Path::new(""));
+        self.mount
+            .iter()
+            .map(|mount| mount.to_string())
+            .chain(
+                relative
+                    .components()
+                    .map(|component| component.as_os_str().to_string_lossy().into_owned()),
+            )
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+}
+
+fn not_found(err: io::Error) -> ApiError {
+    match err.kind() {
+        io::ErrorKind::NotFound => ApiError::new_with_json(404, "Path not found"),
+        _ => err.into(),
+    }
+}
+
+/// Whether `path`, a location below `base`, is inside a staging directory.
+fn is_staging_path(base: &Path, path: &Path) -> bool {
+    path.strip_prefix(base).is_ok_and(|relative| {
+        relative
+            .components()
+            .any(|component| component.as_os_str() == STAGING_DIR)
+    })
+}
+
+/// Whether the deepest existing ancestor of `path`, or `path` itself, resolves to a location
+/// outside `root`.
+fn escapes_root(root: &Path, path: &Path) -> io::Result<bool> {
+    match path.ancestors().find(|ancestor| ancestor.exists()) {
+        Some(existing) => Ok(!existing.canonicalize()?.starts_with(root.canonicalize()?)),
+        None => Ok(true),
+    }
+}
+
+pub fn list_directory(share: &Share, path: &str) -> Result<serde_json::Value, ApiError> {
+    let decoded_path = percent_encoding::percent_decode_str(path).decode_utf8_lossy();
+    if share.is_virtual_root(&decoded_path) {
+        return list_mounts(share.mounts);
+    }
+
+    let directory = share.resolve(path)?;
+    //breadcrumbs are relative to the root, whichever form the requested path took
+    let target_path = Path::new("./").join(directory.client_path(&directory.path));
 
     let mut current_full_path = String::new();
     let mut directory_response = DirectoryInfoResponse {
@@ -85,31 +323,21 @@ pub fn list_directory(path: &str) -> Result<serde_json::Value, ApiError> {
             })
             .collect(),
         files: Vec::new(),
+        read_only: directory.read_only,
     };
 
-    let directory_contents = fs::read_dir(cannonical_target_path)?;
+    let directory_contents = fs::read_dir(&directory.path)?;
 
     for path in directory_contents {
         let path = path?;
-        let path_metadata = path.metadata()?;
-        let system_time: DateTime<Utc> = path_metadata.modified()?.into();
-
-        let file = Files {
-            name: path.file_name().into_string().unwrap(),
-            path: path
-                .path()
-                .strip_prefix(&base_root_path)
-                .map_err(|err| ApiError::new_with_json(500, &err.to_string()))?
-                .to_string_lossy()
-                .into_owned(),
-            file_type: if path.path().is_dir() {
-                FileType::Directory
-            } else {
-                FileType::File
-            },
-            last_modified: system_time.format("%d/%m/%Y %T").to_string(),
-            size: human_bytes(path_metadata.len() as f64),
-        };
+        if path.file_name() == STAGING_DIR {
+            continue;
+        }
+        let file = file_entry(
+            path.file_name().into_string().unwrap(),
+            directory.client_path(&path.path()),
+            &path.path(),
+        )?;
         directory_response.files.push(file);
     }
 
@@ -118,6 +346,889 @@ pub fn list_directory(path: &str) -> Result<serde_json::Value, ApiError> {
     Ok(v)
 }
 
+/// Lists the mounts as the folders of the virtual root, which cannot be written to.
+fn list_mounts(mounts: &[Mount]) -> Result<serde_json::Value, ApiError> {
+    let directory_response = DirectoryInfoResponse {
+        paths: vec![PathParts {
+            part_name: String::from("."),
+            full_path: String::from("./"),
+        }],
+        files: mounts
+            .iter()
+            .map(|mount| file_entry(mount.name.clone(), mount.name.clone(), &mount.path))
+            .collect::<Result<_, _>>()?,
+        read_only: true,
+    };
+    Ok(serde_json::to_value(directory_response)?)
+}
+
+/// Describes the file or directory at `path`, a location in `dir`, as listed by
+/// `list_directory`.
+pub fn file_info(dir: &ResolvedPath, path: &Path) -> Result<serde_json::Value, ApiError> {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let file = file_entry(name.into_owned(), dir.client_path(path), path)?;
+    Ok(serde_json::to_value(file)?)
+}
+
+fn file_entry(name: String, client_path: String, path: &Path) -> Result<Files, ApiError> {
+    let path_metadata = fs::symlink_metadata(path)?;
+    let system_time: DateTime<Utc> = path_metadata.modified()?.into();
+    Ok(Files {
+        name,
+        path: client_path,
+        file_type: if path.is_dir() {
+            FileType::Directory
+        } else {
+            FileType::File
+        },
+        last_modified: system_time.format("%d/%m/%Y %T").to_string(),
+        size: human_bytes(path_metadata.len() as f64),
+    })
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum UploadStatus {
+    Uploaded,
+    Conflict,
+    QuotaExceeded,
+    ChecksumMismatch,
+    UnsupportedType,
+    Failed,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct UploadedFile {
+    name: String,
+    status: UploadStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stored_as: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    size: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sha256: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl UploadedFile {
+    fn failed(name: String, status: UploadStatus, error: String) -> Self {
+        UploadedFile {
+            name,
+            status,
+            stored_as: None,
+            size: None,
+            sha256: None,
+            error: Some(error),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct UploadResponse {
+    files: Vec<UploadedFile>,
+}
+
+/// What to do when an uploaded file already exists in the target directory.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConflictPolicy {
+    Reject,
+    Overwrite,
+    Rename,
+}
+
+pub fn parse_conflict_policy(value: &str) -> Result<ConflictPolicy, String> {
+    match value {
+        "reject" => Ok(ConflictPolicy::Reject),
+        "overwrite" => Ok(ConflictPolicy::Overwrite),
+        "rename" => Ok(ConflictPolicy::Rename),
+        _ => Err(String::from(
+            "The conflict policy must be one of reject, overwrite or rename",
+        )),
+    }
+}
+
+/// Upload settings chosen on the command line.
+pub struct UploadConfig {
+    pub conflict_policy: ConflictPolicy,
+    pub max_size: Option<u64>,
+    /// Byte limits for top-level directories of the served directory, by directory name.
+    pub quotas: HashMap<String, u64>,
+    pub file_types: FileTypeFilter,
+}
+
+/// Parses a byte size such as `512`, `100K`, `20MB` or `4G`, using binary multiples.
+pub fn parse_size(value: &str) -> Result<u64, String> {
+    let invalid = || {
+        format!(
+            "Invalid size '{}', expected a number with an optional K, M, G or T suffix",
+            value
+        )
+    };
+    let value = value.trim();
+    let (number, unit) = value.split_at(
+        value
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(value.len()),
+    );
+    let number = number.parse::<u64>().map_err(|_| invalid())?;
+    let multiplier: u64 = match unit.trim().to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" | "KIB" => 1 << 10,
+        "M" | "MB" | "MIB" => 1 << 20,
+        "G" | "GB" | "GIB" => 1 << 30,
+        "T" | "TB" | "TIB" => 1 << 40,
+        _ => return Err(invalid()),
+    };
+    number.checked_mul(multiplier).ok_or_else(invalid)
+}
+
+/// Parses a `directory=size` quota for a top-level directory.
+pub fn parse_quota(value: &str) -> Result<(String, u64), String> {
+    let (directory, size) = value
+        .split_once('=')
+        .ok_or_else(|| String::from("The format must be directory=size"))?;
+    let directory = directory.trim().trim_matches('/');
+    if directory.is_empty() || directory == ".." || directory == "." || directory.contains('/') {
+        return Err(String::from(
+            "Quotas apply to top-level directories of the served directory",
+        ));
+    }
+    Ok((directory.to_string(), parse_size(size)?))
+}
+
+/// Conflict policy chosen with the `conflict` query parameter, or `default` when absent.
+pub fn requested_conflict_policy(
+    request: &Request,
+    default: ConflictPolicy,
+) -> Result<ConflictPolicy, ApiError> {
+    match request.query("conflict") {
+        Some(policy) => {
+            parse_conflict_policy(policy).map_err(|err| ApiError::new_with_json(400, &err))
+        }
+        None => Ok(default),
+    }
+}
+
+/// Value of a `true`/`false` query parameter, `false` when absent.
+pub fn query_flag(request: &Request, name: &str) -> Result<bool, ApiError> {
+    match request.query(name) {
+        Some(value) => value
+            .parse()
+            .map_err(|_| ApiError::new_with_json(400, &format!("{} must be true or false", name))),
+        None => Ok(false),
+    }
+}
+
+/// Resolves the percent-encoded directory `path` uploads are written to, which must exist
+/// inside the share and be writable.
+pub fn upload_directory(share: &Share, path: &str) -> Result<ResolvedPath, ApiError> {
+    let target_dir = share.resolve(path)?;
+    if !target_dir.path.is_dir() {
+        return Err(ApiError::new_with_json(
+            400,
+            "Upload directory does not exist",
+        ));
+    }
+    target_dir.check_writable()?;
+    Ok(target_dir)
+}
+
+/// Writes every file sent as `multipart/form-data` into `path` and reports the outcome of each.
+/// File names may contain a relative path, as sent for folder uploads, which is recreated
+/// under `path`. Responds with `507` when a file did not fit its directory quota, `422` when
+/// it did not match its expected SHA-256 digest, `415` when its type is not allowed and `409`
+/// when a file was rejected because it already exists.
+pub fn upload_files<R: Read>(
+    content_type: &str,
+    reader: R,
+    share: &Share,
+    path: &str,
+    conflict_policy: ConflictPolicy,
+    config: &UploadConfig,
+) -> Result<HttpResponse, ApiError> {
+    let mut multipart = Multipart::from_content_type(content_type, reader)?;
+    let target_dir = upload_directory(share, path)?;
+
+    let mut upload_response = UploadResponse { files: Vec::new() };
+    let mut quota_usage = QuotaUsage::default();
+    //a sha256 form field holds the expected digest of the file part that follows it
+    let mut expected_sha256 = None;
+    while let Some(mut part) = multipart.next_part()? {
+        let Some(name) = part.file_name().map(str::to_owned) else {
+            if part.name() == Some("sha256") {
+                let mut digest = String::new();
+                part.by_ref().take(128).read_to_string(&mut digest)?;
+                expected_sha256 = Some(parse_hex_sha256(&digest)?);
+            }
+            continue;
+        };
+        let expected_sha256 =
+            match header_sha256(part.header("Content-Digest"), part.header("Digest"))? {
+                Some(digest) => Some(digest),
+                None => expected_sha256.take(),
+            };
+        //parts that cannot be stored are reported and skipped, the rest of the body is still read
+        let uploaded_file = match relative_upload_path(&name) {
+            Ok(relative_path)
+                if escapes_root(&target_dir.base, &target_dir.path.join(&relative_path))? =>
+            {
+                UploadedFile::failed(name, UploadStatus::Failed, OUTSIDE_ROOT.to_string())
+            }
+            Ok(relative_path) => store_upload(
+                &mut part,
+                name,
+                &UploadTarget {
+                    dir: &target_dir,
+                    relative_path: &relative_path,
+                },
+                expected_sha256.as_deref(),
+                conflict_policy,
+                config,
+                &mut quota_usage,
+            )?,
+            Err(error) => UploadedFile::failed(name, UploadStatus::Failed, error),
+        };
+        upload_response.files.push(uploaded_file);
+    }
+
+    if upload_response.files.is_empty() {
+        return Err(ApiError::new_with_json(
+            400,
+            "No file found in multipart request",
+        ));
+    }
+
+    let has_status = |status: fn(&UploadStatus) -> bool| {
+        upload_response
+            .files
+            .iter()
+            .any(|file| status(&file.status))
+    };
+    let status_code = if has_status(|status| matches!(status, UploadStatus::QuotaExceeded)) {
+        507
+    } else if has_status(|status| matches!(status, UploadStatus::ChecksumMismatch)) {
+        422
+    } else if has_status(|status| matches!(status, UploadStatus::UnsupportedType)) {
+        415
+    } else if has_status(|status| matches!(status, UploadStatus::Conflict)) {
+        409
+    } else {
+        200
+    };
+    Ok(HttpResponse::new(
+        Some(Body::Json(serde_json::to_value(upload_response)?)),
+        None,
+        status_code,
+    ))
+}
+
+/// Writes a raw request body to the file at `path` in the share.
+/// Responds with `201` for a new file, `200` when an existing one was replaced, `409`
+/// when the conflict policy rejected it, `415` when its type is not allowed, `422` when it
+/// did not match `expected_sha256` and `507` when it did not fit the directory quota.
+pub fn upload_raw<R: Read>(
+    reader: &mut R,
+    share: &Share,
+    path: &str,
+    expected_sha256: Option<&[u8]>,
+    conflict_policy: ConflictPolicy,
+    config: &UploadConfig,
+) -> Result<HttpResponse, ApiError> {
+    let name = percent_encoding::percent_decode_str(path)
+        .decode_utf8_lossy()
+        .into_owned();
+    let relative_path =
+        relative_upload_path(&name).map_err(|err| ApiError::new_with_json(400, &err))?;
+    let (target_dir, relative_path) = share.locate_new(&relative_path)?;
+    target_dir.check_writable()?;
+    let target_path = target_dir.resolve_new(&relative_path)?;
+    if target_path.is_dir() {
+        return Err(ApiError::new_with_json(
+            400,
+            "Path points to an existing directory",
+        ));
+    }
+    let existed = target_path.exists();
+
+    let uploaded_file = store_upload(
+        reader,
+        name,
+        &UploadTarget {
+            dir: &target_dir,
+            relative_path: &relative_path,
+        },
+        expected_sha256,
+        conflict_policy,
+        config,
+        &mut QuotaUsage::default(),
+    )?;
+    let status_code = match uploaded_file.status {
+        UploadStatus::Uploaded if existed && conflict_policy != ConflictPolicy::Rename => 200,
+        UploadStatus::Uploaded => 201,
+        UploadStatus::Conflict => 409,
+        UploadStatus::QuotaExceeded => 507,
+        UploadStatus::ChecksumMismatch => 422,
+        UploadStatus::UnsupportedType => 415,
+        UploadStatus::Failed => 500,
+    };
+    Ok(HttpResponse::new(
+        Some(Body::Json(serde_json::to_value(uploaded_file)?)),
+        None,
+        status_code,
+    ))
+}
+
+/// Location of one uploaded file, `relative_path` under the upload directory `dir`.
+struct UploadTarget<'a> {
+    dir: &'a ResolvedPath,
+    relative_path: &'a Path,
+}
+
+/// Streams one file into a temporary file in the staging directory and moves it into place once
+/// complete. Errors reading the request are returned, anything else is reported.
+fn store_upload<R: Read>(
+    reader: &mut R,
+    name: String,
+    target: &UploadTarget,
+    expected_sha256: Option<&[u8]>,
+    conflict_policy: ConflictPolicy,
+    config: &UploadConfig,
+    quota_usage: &mut QuotaUsage,
+) -> Result<UploadedFile, ApiError> {
+    let target_path = &target.dir.path.join(target.relative_path);
+    let conflict = || String::from("File already exists");
+    if conflict_policy == ConflictPolicy::Reject && target_path.exists() {
+        return Ok(UploadedFile::failed(
+            name,
+            UploadStatus::Conflict,
+            conflict(),
+        ));
+    }
+
+    let quota = measure_quota(target.dir, target_path, config, quota_usage)?;
+    if let Some(quota) = quota.as_ref().filter(|quota| quota.remaining == 0) {
+        return Ok(quota.exceeded(name));
+    }
+
+    //the type is checked on the first bytes before anything is written to the target directory
+    let mut head = Vec::new();
+    Read::take(&mut *reader, SNIFF_LENGTH).read_to_end(&mut head)?;
+    let file_name = target.relative_path.file_name().unwrap_or_default();
+    if let Err(error) = config.file_types.check(&file_name.to_string_lossy(), &head) {
+        return Ok(UploadedFile::failed(
+            name,
+            UploadStatus::UnsupportedType,
+            error,
+        ));
+    }
+    let reader = io::Cursor::new(head).chain(reader);
+
+    let temp_file = target_path
+        .parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|_| TempUpload::create(&target.dir.staging_dir(), target_path));
+    let mut temp_file = match temp_file {
+        Ok(temp_file) => temp_file,
+        Err(err) => {
+            return Ok(UploadedFile::failed(
+                name,
+                UploadStatus::Failed,
+                err.to_string(),
+            ))
+        }
+    };
+
+    //a failed read drops the temporary file, which removes it. One byte past the limit is
+    //read to tell a file that fits exactly from one that is too large
+    let limit = [config.max_size, quota.as_ref().map(|quota| quota.remaining)]
+        .into_iter()
+        .flatten()
+        .min();
+    let mut reader = HashingReader::new(Read::take(
+        reader,
+        limit.map_or(u64::MAX, |limit| limit.saturating_add(1)),
+    ));
+    let size = io::copy(&mut reader, &mut temp_file.file)?;
+    check_max_size(size, config)?;
+    if let Some(quota) = quota.as_ref().filter(|quota| size > quota.remaining) {
+        return Ok(quota.exceeded(name));
+    }
+
+    //a mismatching file is dropped together with the temporary file
+    let sha256 = to_hex(&reader.finish());
+    if let Some(expected) = expected_sha256
+        .map(to_hex)
+        .filter(|expected| *expected != sha256)
+    {
+        let mut mismatch = UploadedFile::failed(
+            name,
+            UploadStatus::ChecksumMismatch,
+            format!("Expected SHA-256 {}, received {}", expected, sha256),
+        );
+        mismatch.sha256 = Some(sha256);
+        return Ok(mismatch);
+    }
+
+    let stored_path = match claim_path(target_path, conflict_policy, false) {
+        Ok(Some(stored_path)) => stored_path,
+        Ok(None) => {
+            return Ok(UploadedFile::failed(
+                name,
+                UploadStatus::Conflict,
+                conflict(),
+            ))
+        }
+        Err(err) => {
+            return Ok(UploadedFile::failed(
+                name,
+                UploadStatus::Failed,
+                err.to_string(),
+            ))
+        }
+    };
+    let replaced = fs::metadata(&stored_path).map_or(0, |metadata| metadata.len());
+    if let Err(err) = temp_file.persist(&stored_path) {
+        if conflict_policy != ConflictPolicy::Overwrite {
+            release_claim(&stored_path);
+        }
+        return Ok(UploadedFile::failed(
+            name,
+            UploadStatus::Failed,
+            err.to_string(),
+        ));
+    }
+    if let Some(quota) = &quota {
+        quota_usage.record(quota, size, replaced);
+    }
+
+    //reported when the file was renamed or its name had to be sanitized
+    let stored_name = stored_path
+        .strip_prefix(&target.dir.path)
+        .unwrap_or(&stored_path)
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/");
+    let stored_as = (stored_name != name_components(&name).collect::<Vec<_>>().join("/"))
+        .then_some(stored_name);
+    Ok(UploadedFile {
+        name,
+        status: UploadStatus::Uploaded,
+        stored_as,
+        size: Some(size),
+        sha256: Some(sha256),
+        error: None,
+    })
+}
+
+/// Passes data through while computing its SHA-256 digest.
+struct HashingReader<R> {
+    inner: R,
+    hasher: Sha256,
+}
+
+impl<R: Read> HashingReader<R> {
+    fn new(inner: R) -> Self {
+        HashingReader {
+            inner,
+            hasher: Sha256::new(),
+        }
+    }
+
+    fn finish(self) -> Vec<u8> {
+        self.hasher.finalize().to_vec()
+    }
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.hasher.update(&buf[..read]);
+        Ok(read)
+    }
+}
+
+/// Expected SHA-256 digest from a `Content-Digest` (`sha-256=:base64:`) or `Digest`
+/// (`SHA-256=base64`) header. Other algorithms are ignored.
+pub fn header_sha256(
+    content_digest: Option<&str>,
+    digest: Option<&str>,
+) -> Result<Option<Vec<u8>>, ApiError> {
+    let entries = content_digest
+        .into_iter()
+        .chain(digest)
+        .flat_map(|value| value.split(','));
+    for entry in entries {
+        let Some((algorithm, value)) = entry.split_once('=') else {
+            continue;
+        };
+        if algorithm.trim().eq_ignore_ascii_case("sha-256") {
+            return BASE64_STANDARD
+                .decode(value.trim().trim_matches(':'))
+                .ok()
+                .filter(|digest| digest.len() == 32)
+                .map(Some)
+                .ok_or_else(|| ApiError::new_with_json(400, "Invalid SHA-256 digest"));
+        }
+    }
+    Ok(None)
+}
+
+pub fn parse_hex_sha256(value: &str) -> Result<Vec<u8>, ApiError> {
+    let value = value.trim();
+    if value.len() != 64 || !value.is_ascii() {
+        return Err(ApiError::new_with_json(400, "Invalid SHA-256 digest"));
+    }
+    (0..value.len())
+        .step_by(2)
+        .map(|idx| {
+            u8::from_str_radix(&value[idx..idx + 2], 16)
+                .map_err(|_| ApiError::new_with_json(400, "Invalid SHA-256 digest"))
+        })
+        .collect()
+}
+
+/// Hex SHA-256 digest of the file at `path`.
+pub fn file_sha256(path: &Path) -> io::Result<String> {
+    let mut reader = HashingReader::new(File::open(path)?);
+    io::copy(&mut reader, &mut io::sink())?;
+    Ok(to_hex(&reader.finish()))
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Fails with `413` when `size` is over the maximum upload size.
+pub fn check_max_size(size: u64, config: &UploadConfig) -> Result<(), ApiError> {
+    match config.max_size {
+        Some(max_size) if size > max_size => Err(ApiError::new_with_json(
+            413,
+            &format!("File exceeds the maximum upload size of {} bytes", max_size),
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Quota of the top-level directory of the served root a file is uploaded into.
+pub struct DirectoryQuota {
+    pub directory: String,
+    pub limit: u64,
+    pub remaining: u64,
+}
+
+impl DirectoryQuota {
+    fn exceeded(&self, name: String) -> UploadedFile {
+        UploadedFile::failed(
+            name,
+            UploadStatus::QuotaExceeded,
+            format!(
+                "Quota of {} for directory {} exceeded, {} left",
+                human_bytes(self.limit as f64),
+                self.directory,
+                human_bytes(self.remaining as f64)
+            ),
+        )
+    }
+}
+
+/// Space used in quota directories, measured once per request and updated as files are stored
+/// so uploading many files does not walk the same directory for each of them.
+#[derive(Default)]
+struct QuotaUsage {
+    used: HashMap<String, u64>,
+}
+
+impl QuotaUsage {
+    fn record(&mut self, quota: &DirectoryQuota, stored: u64, replaced: u64) {
+        if let Some(used) = self.used.get_mut(&quota.directory) {
+            *used = (*used + stored).saturating_sub(replaced);
+        }
+    }
+}
+
+/// Looks up the quota for the top-level directory containing `target_path`, a location in
+/// `dir`, measuring how much of it is still free. With mounts, the top-level directories are
+/// the mounts themselves.
+pub fn directory_quota(
+    dir: &ResolvedPath,
+    target_path: &Path,
+    config: &UploadConfig,
+) -> Result<Option<DirectoryQuota>, ApiError> {
+    measure_quota(dir, target_path, config, &mut QuotaUsage::default())
+}
+
+fn measure_quota(
+    dir: &ResolvedPath,
+    target_path: &Path,
+    config: &UploadConfig,
+    quota_usage: &mut QuotaUsage,
+) -> Result<Option<DirectoryQuota>, ApiError> {
+    if config.quotas.is_empty() {
+        return Ok(None);
+    }
+    let client_path = dir.client_path(target_path);
+    //files directly in the served directory are not covered by any quota
+    let Some((directory, _)) = client_path.split_once('/') else {
+        return Ok(None);
+    };
+    let Some(&limit) = config.quotas.get(directory) else {
+        return Ok(None);
+    };
+    let directory_path = match dir.mount {
+        Some(_) => dir.base.clone(),
+        None => dir.base.join(directory),
+    };
+
+    let directory = directory.to_string();
+    let used = match quota_usage.used.get(&directory) {
+        Some(&used) => used,
+        None => {
+            let used = match directory_size(&directory_path) {
+                Err(err) if err.kind() == io::ErrorKind::NotFound => 0,
+                used => used?,
+            };
+            quota_usage.used.insert(directory.clone(), used);
+            used
+        }
+    };
+    Ok(Some(DirectoryQuota {
+        directory,
+        limit,
+        remaining: limit.saturating_sub(used),
+    }))
+}
+
+/// Total size of the files below `path`. Uploads still in progress in a staging directory
+/// are not counted, so parallel uploads do not push each other over a quota.
+pub fn directory_size(path: &Path) -> io::Result<u64> {
+    let mut size = 0;
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        if entry.file_name() == STAGING_DIR {
+            continue;
+        }
+        let metadata = entry.metadata()?;
+        size += match metadata.is_dir() {
+            true => directory_size(&entry.path())?,
+            false => metadata.len(),
+        };
+    }
+    Ok(size)
+}
+
+/// Claims the path an entry for `target_path` is stored at under `conflict_policy`, or returns
+/// `None` when it must be rejected because the name is taken. Unless overwriting, an empty file,
+/// or an empty directory for a `directory`, is created at the name first. Creating it fails if
+/// anything exists there, so concurrent uploads can never take the same name; the entry is then
+/// renamed over the placeholder, which must be removed if that fails.
+pub fn claim_path(
+    target_path: &Path,
+    conflict_policy: ConflictPolicy,
+    directory: bool,
+) -> io::Result<Option<PathBuf>> {
+    let claim = |path: &Path| match directory {
+        true => fs::create_dir(path),
+        false => File::create_new(path).map(|_| ()),
+    };
+    loop {
+        let candidate = match conflict_policy {
+            ConflictPolicy::Overwrite => return Ok(Some(target_path.to_path_buf())),
+            ConflictPolicy::Reject => target_path.to_path_buf(),
+            ConflictPolicy::Rename => free_file_name(target_path),
+        };
+        match claim(&candidate) {
+            Ok(()) => return Ok(Some(candidate)),
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {
+                if conflict_policy == ConflictPolicy::Reject {
+                    return Ok(None);
+                }
+            }
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/// Removes the placeholder claimed at `path` by `claim_path` unless something was stored over it.
+pub fn release_claim(path: &Path) {
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.is_dir() => {
+            let _ = fs::remove_dir(path);
+        }
+        Ok(metadata) if metadata.len() == 0 => {
+            let _ = fs::remove_file(path);
+        }
+        _ => {}
+    }
+}
+
+/// Returns `path` if it is free, otherwise the first free `name (n).ext` next to it.
+fn free_file_name(path: &Path) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = path
+        .extension()
+        .map(|extension| format!(".{}", extension.to_string_lossy()))
+        .unwrap_or_default();
+    let mut candidate = path.to_path_buf();
+    let mut counter = 1;
+    while candidate.exists() {
+        candidate = path.with_file_name(format!("{} ({}){}", stem, counter, extension));
+        counter += 1;
+    }
+    candidate
+}
+
+static TEMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// File in the staging directory an upload is written to before being renamed to its final
+/// name, so it never shows up in the share while incomplete. It is deleted when dropped without
+/// being persisted, so interrupted uploads leave nothing behind.
+struct TempUpload {
+    path: PathBuf,
+    file: File,
+    persisted: bool,
+}
+
+impl TempUpload {
+    fn create(staging_dir: &Path, target_path: &Path) -> io::Result<Self> {
+        fs::create_dir_all(staging_dir)?;
+        //shortened so the decorated name stays within file name length limits
+        let file_name: String = target_path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .chars()
+            .take(64)
+            .collect();
+        loop {
+            let nanos = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_nanos();
+            let path = staging_dir.join(format!(
+                "{}.{:x}-{}.upload",
+                file_name,
+                nanos,
+                TEMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+            ));
+            match File::create_new(&path) {
+                Ok(file) => {
+                    return Ok(TempUpload {
+                        path,
+                        file,
+                        persisted: false,
+                    })
+                }
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    fn persist(mut self, target_path: &Path) -> io::Result<()> {
+        self.file.sync_all()?;
+        fs::rename(&self.path, target_path)?;
+        self.persisted = true;
+        Ok(())
+    }
+}
+
+impl Drop for TempUpload {
+    fn drop(&mut self) {
+        if !self.persisted {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+/// Path segments of an uploaded file name, which may use either slash as separator.
+fn name_components(name: &str) -> impl Iterator<Item = &str> {
+    name.split(['/', '\\'])
+        .filter(|component| !matches!(*component, "" | "."))
+}
+
+/// Turns an uploaded file name into a relative path of sanitized segments, rejecting names
+/// that would leave the upload directory.
+pub fn relative_upload_path(name: &str) -> Result<PathBuf, String> {
+    let mut relative_path = PathBuf::new();
+    for component in name_components(name) {
+        if component == ".." {
+            return Err(String::from("File name must not contain '..'"));
+        }
+        relative_path.extend(sanitize_file_name(component));
+    }
+    if relative_path.as_os_str().is_empty() {
+        return Err(String::from("Empty file name"));
+    }
+    Ok(relative_path)
+}
+
+const MAX_FILE_NAME_LENGTH: usize = 255;
+const RESERVED_NAMES: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Makes a single path segment safe to create on any platform. Names are normalized to NFC,
+/// so one sent in decomposed form, as macOS does, names the same file as its composed form.
+/// Control and bidirectional formatting characters, which can disguise an extension, are
+/// dropped, characters Windows does not allow are replaced, trailing dots and spaces are
+/// trimmed, reserved device names are prefixed and long names are shortened while keeping the
+/// extension. Returns `None` when nothing is left.
+fn sanitize_file_name(name: &str) -> Option<String> {
+    let name: String = name
+        .nfc()
+        .filter(|c| !c.is_control() && !is_bidi_format(*c))
+        .map(|c| match c {
+            '<' | '>' | ':' | '"' | '|' | '?' | '*' => '_',
+            c => c,
+        })
+        .collect();
+    let mut name = name.trim().trim_end_matches(['.', ' ']).to_string();
+    if name.is_empty() {
+        return None;
+    }
+
+    let stem = name.split('.').next().unwrap_or_default().trim_end();
+    if RESERVED_NAMES
+        .iter()
+        .any(|reserved| reserved.eq_ignore_ascii_case(stem))
+    {
+        name.insert(0, '_');
+    }
+
+    if name.len() > MAX_FILE_NAME_LENGTH {
+        let extension = name
+            .rfind('.')
+            .filter(|&idx| idx > 0 && name.len() - idx <= 16)
+            .map_or(String::new(), |idx| name[idx..].to_string());
+        let mut end = MAX_FILE_NAME_LENGTH - extension.len();
+        while !name.is_char_boundary(end) {
+            end -= 1;
+        }
+        name.truncate(end);
+        name.push_str(&extension);
+    }
+    Some(name)
+}
+
+fn is_bidi_format(c: char) -> bool {
+    matches!(
+        c,
+        '\u{200e}' | '\u{200f}' | '\u{202a}'..='\u{202e}' | '\u{2066}'..='\u{2069}'
+    )
+}
+
+/// Parses the `--root` directory, resolving it to an absolute path.
+pub fn parse_root(path: &str) -> Result<PathBuf, String> {
+    match Path::new(path).canonicalize() {
+        Ok(root) if root.is_dir() => Ok(root),
+        _ => Err("Root directory not found".to_string()),
+    }
+}
+
 pub fn parse_index_path(path: &str) -> Result<PathBuf, String> {
     let index_path = PathBuf::from(path);
     if index_path.exists() {
@@ -0,0 +1,401 @@
+use std::io::{self, BufRead, BufReader, Read, Write};
+
+use crate::{api_error::ApiError, request::BodySource, BodyTooLarge};
+
+/// Longest chunk size or trailer line accepted, so a client cannot make the server buffer an
+/// endless line.
+const MAX_LINE_LENGTH: u64 = 4096;
+/// Limits on the trailer fields of a chunked body, by count and by total size in bytes.
+const MAX_TRAILERS: usize = 32;
+const MAX_TRAILERS_SIZE: usize = 16 * 1024;
+
+pub(crate) enum BodyLength {
+    Fixed(u64),
+    Chunked,
+}
+
+/// Determines how the body of a request is framed from all of its `header_lines`. Repeated
+/// `Transfer-Encoding` headers or `Content-Length` headers with different values are rejected,
+/// as they let a proxy and the server disagree on where the body ends (RFC 9112 section 6.3).
+pub(crate) fn get_body_length(
+    header_lines: &[(&str, &str)],
+) -> Result<Option<BodyLength>, ApiError> {
+    let values = |name: &str| -> Vec<&str> {
+        header_lines
+            .iter()
+            .filter(|(key, _)| key.trim().eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.trim())
+            .collect()
+    };
+    let encodings = values("Transfer-Encoding");
+    if encodings.len() > 1 {
+        return Err(ApiError::new_with_json(
+            400,
+            "Request must not contain more than one Transfer-Encoding header",
+        ));
+    }
+    //a list of identical lengths, in one header or several, is still a single length
+    let mut lengths: Vec<&str> = values("Content-Length")
+        .into_iter()
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .collect();
+    lengths.dedup();
+    if lengths.len() > 1 {
+        return Err(ApiError::new_with_json(
+            400,
+            "Request must not contain conflicting Content-Length headers",
+        ));
+    }
+
+    match (encodings.first(), lengths.first()) {
+        //a message with both headers can be framed differently by proxies, see RFC 9112 section 6.1
+        (Some(_), Some(_)) => Err(ApiError::new_with_json(
+            400,
+            "Request must not contain both Transfer-Encoding and Content-Length",
+        )),
+        (Some(encoding), None) if encoding.eq_ignore_ascii_case("chunked") => {
+            Ok(Some(BodyLength::Chunked))
+        }
+        (Some(encoding), None) => Err(ApiError::new_with_json(
+            501,
+            &format!("Unsupported transfer encoding: {}", encoding),
+        )),
+        //digits only, as `parse` would also take a sign that other servers may not accept
+        (None, Some(length)) => length
+            .bytes()
+            .all(|byte| byte.is_ascii_digit())
+            .then(|| length.parse::<u64>().ok())
+            .flatten()
+            .map(|length| Some(BodyLength::Fixed(length)))
+            .ok_or_else(|| ApiError::new_with_json(400, "Invalid Content-Length header")),
+        (None, None) => Ok(None),
+    }
+}
+
+enum Framing<R: BufRead> {
+    Fixed(FixedReader<R>),
+    Chunked(ChunkedReader<R>),
+}
+
+/// Request body read straight from the connection, framed by `Content-Length` or chunked
+/// encoding. Reading past `limit` bytes fails with `BodyTooLarge`, which the server answers
+/// with `413 Payload Too Large`.
+pub(crate) struct FramedBody<R: BufRead> {
+    framing: Framing<R>,
+    limit: Option<u64>,
+    read: u64,
+    /// Set once reading failed, after which the position in the connection is unknown.
+    failed: bool,
+}
+
+impl<R: BufRead> FramedBody<R> {
+    pub(crate) fn new(reader: R, length: BodyLength, limit: Option<u64>) -> Self {
+        let framing = match length {
+            BodyLength::Fixed(length) => Framing::Fixed(FixedReader {
+                inner: reader,
+                remaining: length,
+            }),
+            BodyLength::Chunked => Framing::Chunked(ChunkedReader::new(reader)),
+        };
+        FramedBody {
+            framing,
+            limit,
+            read: 0,
+            failed: false,
+        }
+    }
+
+    /// Discards up to `max` bytes the handler left unread so the next request on the
+    /// connection starts at the right position. Returns whether the body was consumed
+    /// completely; otherwise the connection has to be closed rather than receiving the rest
+    /// of a body nobody reads.
+    pub(crate) fn drain(&mut self, max: u64) -> io::Result<bool> {
+        if self.failed {
+            return Ok(false);
+        }
+        io::copy(&mut self.by_ref().take(max), &mut io::sink())?;
+        Ok(self.fill_buf()?.is_empty())
+    }
+
+    fn source(&self) -> &R {
+        match &self.framing {
+            Framing::Fixed(reader) => &reader.inner,
+            Framing::Chunked(reader) => &reader.inner,
+        }
+    }
+}
+
+impl<S: Read + Write> FramedBody<ContinueReader<'_, S>> {
+    /// Whether the client is still waiting for `100 Continue` before sending the body.
+    pub(crate) fn awaits_continue(&self) -> bool {
+        self.source().continue_pending
+    }
+}
+
+/// Connection reader that answers `Expect: 100-continue` once the body is first read, so a
+/// request rejected before its handler reads the body, e.g. by authorization, is never sent.
+pub(crate) struct ContinueReader<'a, S: Read + Write> {
+    reader: &'a mut BufReader<S>,
+    continue_pending: bool,
+}
+
+impl<'a, S: Read + Write> ContinueReader<'a, S> {
+    pub(crate) fn new(reader: &'a mut BufReader<S>, expects_continue: bool) -> Self {
+        ContinueReader {
+            reader,
+            continue_pending: expects_continue,
+        }
+    }
+}
+
+impl<S: Read + Write> Read for ContinueReader<'_, S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let read = available.len().min(buf.len());
+        buf[..read].copy_from_slice(&available[..read]);
+        self.consume(read);
+        Ok(read)
+    }
+}
+
+impl<S: Read + Write> BufRead for ContinueReader<'_, S> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.continue_pending {
+            let stream = self.reader.get_mut();
+            stream.write_all(b"HTTP/1.1 100 Continue\r\n\r\n")?;
+            stream.flush()?;
+            self.continue_pending = false;
+        }
+        self.reader.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.reader.consume(amt)
+    }
+}
+
+impl<R: BufRead> Read for FramedBody<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let read = available.len().min(buf.len());
+        buf[..read].copy_from_slice(&available[..read]);
+        self.consume(read);
+        Ok(read)
+    }
+}
+
+impl<R: BufRead> BufRead for FramedBody<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        let (read, limit) = (self.read, self.limit);
+        let available = match &mut self.framing {
+            Framing::Fixed(reader) => reader.fill_buf(),
+            Framing::Chunked(reader) => reader.fill_buf(),
+        };
+        let available = match available {
+            Ok(available) => available,
+            Err(err) => {
+                self.failed = true;
+                return Err(err);
+            }
+        };
+        match limit {
+            Some(limit) if !available.is_empty() && read >= limit => {
+                self.failed = true;
+                Err(io::Error::other(BodyTooLarge { limit }))
+            }
+            Some(limit) => {
+                let allowed = (limit - read).try_into().unwrap_or(usize::MAX);
+                Ok(&available[..available.len().min(allowed)])
+            }
+            None => Ok(available),
+        }
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.read += amt as u64;
+        match &mut self.framing {
+            Framing::Fixed(reader) => reader.consume(amt),
+            Framing::Chunked(reader) => reader.consume(amt),
+        }
+    }
+}
+
+impl<R: BufRead> BodySource for FramedBody<R> {
+    fn trailers(&self) -> &[(String, String)] {
+        match &self.framing {
+            Framing::Fixed(_) => &[],
+            Framing::Chunked(reader) => &reader.trailers,
+        }
+    }
+}
+
+/// Reads a body of `remaining` bytes framed by `Content-Length`. A connection closed before
+/// all of them arrived fails with `UnexpectedEof` instead of looking like the end of the body,
+/// so a truncated upload is never stored.
+struct FixedReader<R: BufRead> {
+    inner: R,
+    remaining: u64,
+}
+
+impl<R: BufRead> BufRead for FixedReader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.remaining == 0 {
+            return Ok(&[]);
+        }
+        let remaining = self.remaining.try_into().unwrap_or(usize::MAX);
+        let available = self.inner.fill_buf()?;
+        if available.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "Connection closed before the whole body was received",
+            ));
+        }
+        Ok(&available[..available.len().min(remaining)])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.inner.consume(amt);
+        self.remaining -= amt as u64;
+    }
+}
+
+impl<R: BufRead> Read for FixedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let read = available.len().min(buf.len());
+        buf[..read].copy_from_slice(&available[..read]);
+        self.consume(read);
+        Ok(read)
+    }
+}
+
+/// Decodes a `Transfer-Encoding: chunked` request body, collecting any trailer fields.
+struct ChunkedReader<R: BufRead> {
+    inner: R,
+    remaining: u64,
+    in_chunk: bool,
+    finished: bool,
+    trailers: Vec<(String, String)>,
+}
+
+impl<R: BufRead> ChunkedReader<R> {
+    fn new(inner: R) -> Self {
+        ChunkedReader {
+            inner,
+            remaining: 0,
+            in_chunk: false,
+            finished: false,
+            trailers: Vec::new(),
+        }
+    }
+
+    fn read_line(&mut self) -> io::Result<String> {
+        let mut line = Vec::new();
+        (&mut self.inner)
+            .take(MAX_LINE_LENGTH)
+            .read_until(b'\n', &mut line)?;
+        if !line.ends_with(b"\n") {
+            return Err(match line.len() as u64 {
+                MAX_LINE_LENGTH => io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Line in chunked body is too long",
+                ),
+                _ => io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "Connection closed inside chunked body",
+                ),
+            });
+        }
+        String::from_utf8(line).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+
+    fn read_chunk_size(&mut self) -> io::Result<u64> {
+        let line = self.read_line()?;
+        //chunk extensions after ';' are ignored
+        let size = line.split(';').next().unwrap_or("").trim();
+        //hex digits only, as `from_str_radix` would also take a sign
+        size.bytes()
+            .all(|byte| byte.is_ascii_hexdigit())
+            .then(|| u64::from_str_radix(size, 16).ok())
+            .flatten()
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Invalid chunk size: {}", size),
+                )
+            })
+    }
+
+    fn read_trailers(&mut self) -> io::Result<()> {
+        let mut size = 0;
+        loop {
+            let line = self.read_line()?;
+            let line = line.trim_end_matches(['\r', '\n']);
+            if line.is_empty() {
+                return Ok(());
+            }
+            size += line.len();
+            if self.trailers.len() >= MAX_TRAILERS || size > MAX_TRAILERS_SIZE {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Too many trailer fields",
+                ));
+            }
+            let (key, value) = line.split_once(':').ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidData, "Invalid trailer field")
+            })?;
+            self.trailers
+                .push((key.trim().to_string(), value.trim().to_string()));
+        }
+    }
+}
+
+impl<R: BufRead> BufRead for ChunkedReader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.finished {
+            return Ok(&[]);
+        }
+        if self.remaining == 0 {
+            //every chunk's data is terminated by CRLF
+            if self.in_chunk && !self.read_line()?.trim().is_empty() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Missing CRLF after chunk data",
+                ));
+            }
+            self.remaining = self.read_chunk_size()?;
+            self.in_chunk = true;
+            if self.remaining == 0 {
+                self.read_trailers()?;
+                self.finished = true;
+                return Ok(&[]);
+            }
+        }
+
+        let remaining = self.remaining.try_into().unwrap_or(usize::MAX);
+        let available = self.inner.fill_buf()?;
+        if available.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "Connection closed inside chunked body",
+            ));
+        }
+        Ok(&available[..available.len().min(remaining)])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.inner.consume(amt);
+        self.remaining -= amt as u64;
+    }
+}
+
+impl<R: BufRead> Read for ChunkedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let read = available.len().min(buf.len());
+        buf[..read].copy_from_slice(&available[..read]);
+        self.consume(read);
+        Ok(read)
+    }
+}
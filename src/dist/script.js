@@ -2,12 +2,14 @@ const pathElem  = document.querySelector('.path-wrapper div');
 const upButton = document.getElementById('up-button');
 const uploadProgress = document.getElementById('upload-progress');
 const uploadForm = document.getElementById('upload-form');
+const newFolderButton = document.getElementById('new-folder-button');
 let currentPathElem = pathElem.lastChild;
 
 uploadForm.reset();
 
 let currentFiles = [];
 let currentPaths = [];
+let currentReadOnly = false;
 
 function renderPath(pathArray) {
     pathElem.innerHTML = '';
@@ -47,7 +49,7 @@ async function renderFileTree(fileArray) {
                 history.pushState({path: file.path}, '', file.path);
             }
         } else {
-            fileLink.href = `/api/files?path=${file.path}`;
+            fileLink.href = `/api/files?path=${encodeURIComponent(file.path)}`;
             fileLink.textContent = file.name;
         }
         name.appendChild(fileLink);
@@ -58,12 +60,23 @@ async function renderFileTree(fileArray) {
         tr.appendChild(name);
         tr.appendChild(lastModified);
         tr.appendChild(size);
+        const actions = document.createElement('td');
+        const moveButton = document.createElement('button');
+        moveButton.textContent = 'Move';
+        moveButton.disabled = currentReadOnly;
+        moveButton.onclick = () => onTransferClick(file, 'move');
+        actions.appendChild(moveButton);
+        const copyButton = document.createElement('button');
+        copyButton.textContent = 'Copy';
+        copyButton.onclick = () => onTransferClick(file, 'copy');
+        actions.appendChild(copyButton);
+        tr.appendChild(actions);
         tbody.appendChild(tr);
     });
 };
 
 async function fetchDirectory(path = "./") {
-    const files = await fetch(`/api/directory?path=${path}`);
+    const files = await fetch(`/api/directory?path=${encodeURIComponent(path)}`);
     if (!files.ok) {
         const html = document.querySelector('html');
         html.innerHTML = await files.text();
@@ -71,8 +84,11 @@ async function fetchDirectory(path = "./") {
         const directory = await files.json();
         currentFiles = directory.files;
         currentPaths = directory.paths;
+        currentReadOnly = directory.read_only;
         renderFileTree(currentFiles);
         renderPath(currentPaths);
+        uploadForm.querySelectorAll('input, button').forEach(input => input.disabled = directory.read_only);
+        newFolderButton.disabled = directory.read_only;
     
         if (currentPaths.length === 1) {
             upButton.setAttribute('disabled', true);
@@ -83,6 +99,31 @@ async function fetchDirectory(path = "./") {
 
 }
 
+async function onTransferClick(file, action) {
+    const target = prompt(`${action === 'move' ? 'Move' : 'Copy'} ${file.name} to:`, file.path);
+    if (!target || target === file.path) return;
+    const response = await fetch(`/api/files/${action}?from=${encodeURIComponent(file.path)}&to=${encodeURIComponent(target)}`, { method: 'POST' });
+    if (!response.ok) {
+        alert(`${file.name} could not be ${action === 'move' ? 'moved' : 'copied'}: ${await errorMessage(response)}`);
+    }
+    fetchDirectory(currentPaths[currentPaths.length - 1].full_path);
+}
+
+async function onNewFolderClick() {
+    const name = prompt('Folder name:');
+    if (!name) return;
+    const path = `${currentPaths[currentPaths.length - 1].full_path}${name}`;
+    const response = await fetch(`/api/directory?path=${encodeURIComponent(path)}&parents=true`, { method: 'POST' });
+    if (!response.ok) {
+        alert(`The folder could not be created: ${await errorMessage(response)}`);
+    }
+    fetchDirectory(currentPaths[currentPaths.length - 1].full_path);
+}
+
+function errorMessage(response) {
+    return response.json().then(body => body.message, () => response.statusText);
+}
+
 function onUpClick() {
     fetchDirectory(currentPaths[currentPaths.length - 2].full_path);
     history.back();
@@ -96,9 +137,15 @@ document.getElementById('upload-form').addEventListener('submit', (event) => {
     event.preventDefault();
     const progressValue = uploadProgress.firstElementChild;
     const targetPath = currentPaths[currentPaths.length - 1].full_path;
-    const file = document.querySelector("#upload-form input[type='file']");
+    const inputs = document.querySelectorAll("#upload-form input[type='file']");
     var formData = new FormData();
-    formData.append('file', file.files[0]);
+    inputs.forEach(input => {
+        for (const file of input.files) {
+            //folder uploads keep their relative path so the directory structure is recreated
+            formData.append('file', file, file.webkitRelativePath || file.name);
+        }
+    });
+    if (!formData.has('file')) return;
 
     var xhr = new XMLHttpRequest();
 
@@ -108,17 +155,28 @@ document.getElementById('upload-form').addEventListener('submit', (event) => {
             progressValue.innerHTML = `${percentComplete}%`;
         }
     }
-    xhr.open('POST', "upload", true);
-    xhr.setRequestHeader('Path', targetPath);
+    xhr.open('POST', `/api/upload?path=${encodeURIComponent(targetPath)}`, true);
 
     xhr.onload = function () {
-        if (xhr.status === 200) {
-            alert('File uploaded successfully.');
+        //responses for rejected files (409, 415, 422, 507) still carry the report of every file
+        let report = null;
+        try {
+            report = JSON.parse(xhr.responseText);
+        } catch {
+            //not a report, e.g. a plain error page
+        }
+        if (Array.isArray(report?.files)) {
+            const failed = report.files.filter(file => file.status !== 'uploaded');
+            if (failed.length === 0) {
+                alert('Files uploaded successfully.');
+            } else {
+                alert(`Some files could not be uploaded:\n${failed.map(file => `${file.name}: ${file.error}`).join('\n')}`);
+            }
             fetchDirectory(targetPath);
             uploadForm.reset();
             uploadProgress.classList.add('d-none');
         } else {
-            alert('An error occurred while uploading the file.');
+            alert(`An error occurred while uploading the files: ${report?.message || xhr.statusText}`);
         }
     };
 
@@ -132,7 +190,7 @@ window.addEventListener('popstate', (event) => {
 });
 
 if (!history.state && window.location.pathname !== '/') {
-    fetchDirectory(`.${window.location.pathname}`);
+    fetchDirectory(decodeURIComponent(`.${window.location.pathname}`));
 } else {
     fetchDirectory(history.state?.path || "./");
 }
@@ -1,7 +1,9 @@
 use std::fmt;
 use std::ops::{Deref, DerefMut};
 
-use crate::{http_parse_error::HttpParseError, Body, HttpResponse};
+use crate::{
+    http_parse_error::HttpParseError, Body, BodyTooLarge, HttpResponse, MalformedMultipart,
+};
 
 #[derive(Debug)]
 pub struct ApiError(Box<ApiErrorImpl>);
@@ -71,6 +73,18 @@ impl std::error::Error for ApiError {}
 
 impl From<std::io::Error> for ApiError {
     fn from(error: std::io::Error) -> Self {
+        if let Some(too_large) = error
+            .get_ref()
+            .and_then(|inner| inner.downcast_ref::<BodyTooLarge>())
+        {
+            return ApiError::new_with_json(413, &too_large.to_string());
+        }
+        if let Some(malformed) = error
+            .get_ref()
+            .and_then(|inner| inner.downcast_ref::<MalformedMultipart>())
+        {
+            return ApiError::new_with_json(400, &malformed.to_string());
+        }
         ApiError::new_with_html(500, &format!("IO Error: {}", error))
     }
 }
@@ -154,6 +168,7 @@ fn get_cannonical_reason<'a>(status_code: u16) -> &'a str {
         200 => "OK",
         201 => "Created",
         204 => "No Content",
+        206 => "Partial Content",
         301 => "Moved Permanently",
         302 => "Found",
         304 => "Not Modified",
@@ -162,10 +177,17 @@ fn get_cannonical_reason<'a>(status_code: u16) -> &'a str {
         403 => "Forbidden",
         404 => "Not Found",
         405 => "Method Not Allowed",
+        409 => "Conflict",
+        412 => "Precondition Failed",
+        413 => "Payload Too Large",
+        415 => "Unsupported Media Type",
+        416 => "Range Not Satisfiable",
+        422 => "Unprocessable Content",
         500 => "Internal Server Error",
         501 => "Not Implemented",
         502 => "Bad Gateway",
         503 => "Service Unavailable",
+        507 => "Insufficient Storage",
         _ => "Unknown Status Code",
     }
 }
@@ -1,12 +1,14 @@
 use std::{
-    fs::File,
-    io::{self, BufReader, Write},
+    fs::{File, Metadata},
+    io::{self, BufReader, Read, Seek, SeekFrom, Write},
+    time::{SystemTime, UNIX_EPOCH},
 };
 
+use chrono::{DateTime, Utc};
 use flate2::write::GzEncoder;
 use flate2::Compression;
 
-use crate::ReadWrite;
+use crate::{api_error::ApiError, ReadWrite};
 
 #[derive(Debug)]
 pub enum Body {
@@ -23,6 +25,21 @@ pub struct HttpResponse {
     pub body: Option<Body>,
     pub status_code: u16,
     pub headers: Vec<(String, String)>,
+    ranges: Vec<ByteRange>,
+    head_only: bool,
+}
+
+/// Inclusive byte range of a file-backed body, as selected by a `Range` request header.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ByteRange {
+    pub start: u64,
+    pub end: u64,
+}
+
+impl ByteRange {
+    fn len(&self) -> u64 {
+        self.end - self.start + 1
+    }
 }
 
 impl HttpResponse {
@@ -32,6 +49,8 @@ impl HttpResponse {
             body,
             status_code,
             headers: Vec::new(),
+            ranges: Vec::new(),
+            head_only: false,
         }
     }
     pub fn write_response(
@@ -39,13 +58,25 @@ impl HttpResponse {
         stream: &mut Box<dyn ReadWrite>,
         compress: bool,
     ) -> Result<(), Box<dyn std::error::Error>> {
+        let boundary = format!(
+            "{:x}",
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_nanos())
+                .unwrap_or_default()
+        );
+        let content_type = if self.ranges.len() > 1 {
+            format!("multipart/byteranges; boundary={}", boundary)
+        } else {
+            self.content_type.clone()
+        };
+
         let mut base_headers = format!(
             "HTTP/1.1 {}\r\n\
             Content-Type: {}\r\n\
-            Connection: keep-alive\r\n\
             Server: RustHttpServer/1.0\r\n\
             ",
-            self.status_code, self.content_type
+            self.status_code, content_type
         );
 
         self.headers.iter().for_each(|(key, value)| {
@@ -53,19 +84,40 @@ impl HttpResponse {
         });
 
         if let Some(body) = self.body {
+            //partial content is sent as is, compressing it would change the meaning of the ranges
+            if !self.ranges.is_empty() {
+                if let Body::DownloadStream(file, _) | Body::FileStream(file) = body {
+                    let ranged_body = RangedBody {
+                        ranges: &self.ranges,
+                        content_type: &self.content_type,
+                        boundary: &boundary,
+                    };
+                    return handle_ranged_file_stream(
+                        file,
+                        ranged_body,
+                        base_headers,
+                        stream,
+                        self.head_only,
+                    );
+                }
+            }
             return match (body, compress) {
                 (Body::DownloadStream(file, name), _) => {
-                    handle_file_stream(file, Some(name), base_headers, stream, true)
+                    handle_file_stream(file, Some(name), base_headers, stream, true, self.head_only)
                 }
                 (Body::FileStream(file), true) => {
-                    handle_compressed_file_stream(file, base_headers, stream)
+                    handle_compressed_file_stream(file, base_headers, stream, self.head_only)
                 }
-                (Body::FileStream(file), false) => {
-                    handle_file_stream(file, None, base_headers, stream, false)
-                }
-                (Body::Text(text), should_compress) => {
-                    write_buffered_body(base_headers, text.as_bytes(), should_compress, stream)
+                (Body::FileStream(file), _) => {
+                    handle_file_stream(file, None, base_headers, stream, false, self.head_only)
                 }
+                (Body::Text(text), should_compress) => write_buffered_body(
+                    base_headers,
+                    text.as_bytes(),
+                    should_compress,
+                    stream,
+                    self.head_only,
+                ),
                 (Body::Json(json), should_compress) => {
                     let serialized = json.to_string();
                     write_buffered_body(
@@ -73,16 +125,119 @@ impl HttpResponse {
                         serialized.as_bytes(),
                         should_compress,
                         stream,
+                        self.head_only,
                     )
                 }
                 (Body::StaticFile(file, _), should_compress) => {
-                    write_buffered_body(base_headers, file, should_compress, stream)
+                    write_buffered_body(base_headers, file, should_compress, stream, self.head_only)
                 }
             };
         }
 
+        //responses without a body still need their headers terminated
+        if self.status_code != 204 && self.status_code != 304 {
+            base_headers.push_str("Content-Length: 0\r\n");
+        }
+        base_headers.push_str("\r\n");
+        stream.write_all(base_headers.as_bytes())?;
+
         Ok(())
     }
+
+    /// Narrows a `200` file-backed response to the ranges requested by the `Range` header.
+    /// Syntactically invalid headers and failed `If-Range` validation fall back to the full
+    /// body, while ranges that lie entirely outside the file produce a `416` response.
+    pub fn with_range(mut self, range: &str, if_range: Option<&str>) -> Self {
+        if self.status_code != 200 {
+            return self;
+        }
+        let Some(Body::DownloadStream(file, _) | Body::FileStream(file)) = &self.body else {
+            return self;
+        };
+        let Ok(metadata) = file.metadata() else {
+            return self;
+        };
+        if let Some(if_range) = if_range {
+            let matches = Validators::from_metadata(&metadata)
+                .is_some_and(|validators| validators.matches_if_range(if_range));
+            if !matches {
+                return self;
+            }
+        }
+
+        let file_size = metadata.len();
+        match parse_range_header(range, file_size) {
+            Some(ranges) if ranges.is_empty() => {
+                ApiError::new_with_json(416, "Requested range not satisfiable")
+                    .into_response()
+                    .add_response_header("Content-Range", &format!("bytes */{}", file_size))
+            }
+            Some(ranges) => {
+                self.status_code = 206;
+                self.ranges = ranges;
+                self
+            }
+            None => self,
+        }
+    }
+
+    /// Adds `ETag` and `Last-Modified` validators to `200` file-backed and embedded responses
+    /// and turns the response into a `304 Not Modified` when the request's `If-None-Match`
+    /// or, in its absence, `If-Modified-Since` condition shows the client copy is current.
+    pub fn with_validators(
+        mut self,
+        if_none_match: Option<&str>,
+        if_modified_since: Option<&str>,
+    ) -> Self {
+        if self.status_code != 200 {
+            return self;
+        }
+        let Some(validators) = self.body.as_ref().and_then(Validators::from_body) else {
+            return self;
+        };
+
+        let not_modified = match (if_none_match, if_modified_since) {
+            (Some(if_none_match), _) => validators.matches_if_none_match(if_none_match),
+            (None, Some(if_modified_since)) => validators.not_modified_since(if_modified_since),
+            (None, None) => false,
+        };
+
+        self = self.add_response_header("ETag", &validators.etag);
+        if let Some(last_modified) = validators.last_modified {
+            self = self.add_response_header("Last-Modified", &format_http_date(last_modified));
+        }
+        if not_modified {
+            self.status_code = 304;
+            self.body = None;
+        }
+        self
+    }
+
+    /// Marks the response as the answer to a HEAD request: headers, including the
+    /// `Content-Length` of the body, are written but the body itself is not.
+    pub fn into_head_response(mut self) -> Self {
+        self.head_only = true;
+        self
+    }
+
+    /// Marks the response as one of the variants served when compression is enabled: caches
+    /// are told it depends on `Accept-Encoding`, and a `gzip` body gets its own `ETag`, as it
+    /// is not the same representation as the uncompressed one. Downloads and partial content
+    /// are never compressed and are left as they are.
+    pub fn with_encoding(mut self, gzip: bool) -> Self {
+        if !self.ranges.is_empty() || matches!(self.body, Some(Body::DownloadStream(..))) {
+            return self;
+        }
+        if gzip {
+            for (key, value) in &mut self.headers {
+                if key == "ETag" {
+                    *value = gzip_etag(value);
+                }
+            }
+        }
+        self.add_response_header("Vary", "Accept-Encoding")
+    }
+
     pub fn add_response_header(mut self, key: &str, value: &str) -> Self {
         self.headers.push((key.to_string(), value.to_string()));
         self
@@ -95,11 +250,13 @@ fn handle_file_stream(
     mut headers: String,
     mut stream: &mut Box<dyn ReadWrite>,
     is_attachment: bool,
+    head_only: bool,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let metadata = file.metadata()?;
     let file_size = metadata.len();
 
     headers.push_str(&format!("Content-Length: {}\r\n", file_size));
+    headers.push_str("Accept-Ranges: bytes\r\n");
 
     if is_attachment {
         headers.push_str(&format!(
@@ -110,43 +267,302 @@ fn handle_file_stream(
     headers.push_str("\r\n");
 
     stream.write_all(headers.as_bytes())?;
+    if head_only {
+        return Ok(());
+    }
     let mut reader = BufReader::new(file);
     io::copy(&mut reader, &mut stream)?;
     Ok(())
 }
 
+struct RangedBody<'a> {
+    ranges: &'a [ByteRange],
+    content_type: &'a str,
+    boundary: &'a str,
+}
+
+fn handle_ranged_file_stream(
+    mut file: File,
+    body: RangedBody,
+    mut headers: String,
+    stream: &mut Box<dyn ReadWrite>,
+    head_only: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let file_size = file.metadata()?.len();
+    headers.push_str("Accept-Ranges: bytes\r\n");
+
+    if let [range] = body.ranges {
+        headers.push_str(&format!(
+            "Content-Range: bytes {}-{}/{}\r\n",
+            range.start, range.end, file_size
+        ));
+        headers.push_str(&format!("Content-Length: {}\r\n", range.len()));
+        headers.push_str("\r\n");
+        stream.write_all(headers.as_bytes())?;
+        if head_only {
+            return Ok(());
+        }
+
+        file.seek(SeekFrom::Start(range.start))?;
+        io::copy(&mut BufReader::new(file).take(range.len()), stream)?;
+        return Ok(());
+    }
+
+    //every part carries its own headers, so the total length is known before writing
+    let part_headers: Vec<String> = body
+        .ranges
+        .iter()
+        .map(|range| {
+            format!(
+                "\r\n--{}\r\nContent-Type: {}\r\nContent-Range: bytes {}-{}/{}\r\n\r\n",
+                body.boundary, body.content_type, range.start, range.end, file_size
+            )
+        })
+        .collect();
+    let closing_delimiter = format!("\r\n--{}--\r\n", body.boundary);
+    let content_length = body.ranges.iter().map(ByteRange::len).sum::<u64>()
+        + part_headers.iter().map(|h| h.len() as u64).sum::<u64>()
+        + closing_delimiter.len() as u64;
+
+    headers.push_str(&format!("Content-Length: {}\r\n", content_length));
+    headers.push_str("\r\n");
+    stream.write_all(headers.as_bytes())?;
+    if head_only {
+        return Ok(());
+    }
+
+    for (range, part_header) in body.ranges.iter().zip(part_headers) {
+        stream.write_all(part_header.as_bytes())?;
+        file.seek(SeekFrom::Start(range.start))?;
+        io::copy(&mut (&mut file).take(range.len()), stream)?;
+    }
+    stream.write_all(closing_delimiter.as_bytes())?;
+
+    Ok(())
+}
+
+/// Parses a `bytes=` range set against a body of `file_size` bytes. Returns `None` if the
+/// header is invalid or empty and should be ignored, and an empty list if no range is
+/// satisfiable. Overlapping and adjacent ranges are merged, in ascending order.
+fn parse_range_header(header: &str, file_size: u64) -> Option<Vec<ByteRange>> {
+    let specs: Vec<&str> = header
+        .trim()
+        .strip_prefix("bytes=")?
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .collect();
+    if specs.is_empty() {
+        return None;
+    }
+    let mut ranges: Vec<ByteRange> = Vec::new();
+
+    for spec in specs {
+        let (start, end) = spec.split_once('-')?;
+        let (start, end) = (start.trim(), end.trim());
+
+        let range = if start.is_empty() {
+            let suffix_length = end.parse::<u64>().ok()?;
+            if suffix_length == 0 || file_size == 0 {
+                continue;
+            }
+            ByteRange {
+                start: file_size.saturating_sub(suffix_length),
+                end: file_size - 1,
+            }
+        } else {
+            let start = start.parse::<u64>().ok()?;
+            let end = match end {
+                "" => u64::MAX,
+                end => end.parse::<u64>().ok()?,
+            };
+            if end < start {
+                return None;
+            }
+            if start >= file_size {
+                continue;
+            }
+            ByteRange {
+                start,
+                end: end.min(file_size - 1),
+            }
+        };
+        ranges.push(range);
+    }
+
+    //repeating or overlapping ranges would otherwise send the same bytes several times
+    ranges.sort_by_key(|range| range.start);
+    let mut merged: Vec<ByteRange> = Vec::with_capacity(ranges.len());
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if range.start <= last.end.saturating_add(1) => {
+                last.end = last.end.max(range.end);
+            }
+            _ => merged.push(range),
+        }
+    }
+    let ranges = merged;
+
+    //guard against requests that try to amplify a response with many tiny ranges
+    if ranges.len() > MAX_RANGES {
+        return None;
+    }
+    Some(ranges)
+}
+
+const MAX_RANGES: usize = 32;
+
+struct Validators {
+    etag: String,
+    last_modified: Option<SystemTime>,
+}
+
+impl Validators {
+    fn from_body(body: &Body) -> Option<Self> {
+        match body {
+            Body::DownloadStream(file, _) | Body::FileStream(file) => {
+                Validators::from_metadata(&file.metadata().ok()?)
+            }
+            //embedded files never change for a given binary, so their content alone identifies them
+            Body::StaticFile(contents, _) => Some(Validators {
+                etag: format!("\"{:016x}\"", fnv1a_hash(contents)),
+                last_modified: None,
+            }),
+            _ => None,
+        }
+    }
+
+    fn from_metadata(metadata: &Metadata) -> Option<Self> {
+        let modified = metadata.modified().ok()?;
+        let modified_nanos = modified.duration_since(UNIX_EPOCH).ok()?.as_nanos();
+        Some(Validators {
+            etag: format!("\"{:x}-{:x}\"", metadata.len(), modified_nanos),
+            last_modified: Some(modified),
+        })
+    }
+
+    fn matches_if_none_match(&self, if_none_match: &str) -> bool {
+        //If-None-Match uses weak comparison, so a W/ prefix on either side is ignored. The tag
+        //of the compressed variant validates it as well
+        let etag = self.etag.trim_start_matches("W/");
+        let gzip_etag = gzip_etag(etag);
+        if_none_match.split(',').map(str::trim).any(|tag| {
+            let tag = tag.trim_start_matches("W/");
+            tag == "*" || tag == etag || tag == gzip_etag
+        })
+    }
+
+    fn not_modified_since(&self, if_modified_since: &str) -> bool {
+        let (Some(last_modified), Ok(since)) = (
+            self.last_modified,
+            DateTime::parse_from_rfc2822(if_modified_since.trim()),
+        ) else {
+            return false;
+        };
+        DateTime::<Utc>::from(last_modified).timestamp() <= since.timestamp()
+    }
+
+    fn matches_if_range(&self, if_range: &str) -> bool {
+        let if_range = if_range.trim();
+        //If-Range requires a strong match, weak entity tags never select a range
+        if if_range.starts_with('"') {
+            return if_range == self.etag;
+        }
+        let (Some(last_modified), Ok(date)) =
+            (self.last_modified, DateTime::parse_from_rfc2822(if_range))
+        else {
+            return false;
+        };
+        DateTime::<Utc>::from(last_modified).timestamp() == date.timestamp()
+    }
+}
+
+/// Entity tag of the gzip-compressed variant of the body tagged `etag`.
+fn gzip_etag(etag: &str) -> String {
+    format!("{}-gzip\"", etag.strip_suffix('"').unwrap_or(etag))
+}
+
+fn fnv1a_hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+fn format_http_date(time: SystemTime) -> String {
+    DateTime::<Utc>::from(time)
+        .format("%a, %d %b %Y %H:%M:%S GMT")
+        .to_string()
+}
+
 fn handle_compressed_file_stream(
     file: File,
     mut headers: String,
     stream: &mut Box<dyn ReadWrite>,
+    head_only: bool,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    if headers.contains("Connection: keep-alive") {
-        headers = headers.replace("Connection: keep-alive", "Connection: close");
-    }
+    //the compressed length is unknown upfront, so the body is sent in chunks. A HEAD response
+    //announces the same framing as the GET response would
     headers.push_str("Content-Encoding: gzip\r\n");
-    headers.push_str("Vary: Accept-Encoding\r\n");
+    headers.push_str("Transfer-Encoding: chunked\r\n");
     headers.push_str("\r\n");
 
     stream.write_all(headers.as_bytes())?;
+    if head_only {
+        return Ok(());
+    }
 
-    let mut encoder = GzEncoder::new(stream, Compression::default());
+    let mut encoder = GzEncoder::new(ChunkedWriter::new(stream), Compression::default());
     let mut reader = BufReader::new(file);
-    println!("headers: {}", headers);
     io::copy(&mut reader, &mut encoder)?;
-    encoder.finish()?;
+    encoder.finish()?.finish()?;
 
     Ok(())
 }
 
+/// Frames everything written to it as HTTP/1.1 chunks. Empty writes are skipped because a
+/// zero-sized chunk marks the end of the body, which is only written by `finish`.
+pub struct ChunkedWriter<W: Write> {
+    inner: W,
+}
+
+impl<W: Write> ChunkedWriter<W> {
+    pub fn new(inner: W) -> Self {
+        ChunkedWriter { inner }
+    }
+
+    pub fn finish(mut self) -> io::Result<W> {
+        self.inner.write_all(b"0\r\n\r\n")?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for ChunkedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        self.inner
+            .write_all(format!("{:x}\r\n", buf.len()).as_bytes())?;
+        self.inner.write_all(buf)?;
+        self.inner.write_all(b"\r\n")?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
 fn write_buffered_body(
     mut headers: String,
     body: &[u8],
     compress: bool,
     stream: &mut Box<dyn ReadWrite>,
+    head_only: bool,
 ) -> Result<(), Box<dyn std::error::Error>> {
     if compress {
         headers.push_str("Content-Encoding: gzip\r\n");
-        headers.push_str("Vary: Accept-Encoding\r\n");
 
         let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
         encoder.write_all(body)?;
@@ -155,14 +571,18 @@ fn write_buffered_body(
         headers.push_str(&format!("Content-Length: {}\r\n", encoded.len()));
         headers.push_str("\r\n");
         stream.write_all(headers.as_bytes())?;
-        stream.write_all(&encoded)?;
+        if !head_only {
+            stream.write_all(&encoded)?;
+        }
         return Ok(());
     }
 
     headers.push_str(&format!("Content-Length: {}\r\n", body.len()));
     headers.push_str("\r\n");
     stream.write_all(headers.as_bytes())?;
-    stream.write_all(body)?;
+    if !head_only {
+        stream.write_all(body)?;
+    }
 
     Ok(())
 }
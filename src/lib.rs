@@ -1,27 +1,35 @@
 use api_error::ApiError;
+use body::{get_body_length, BodyLength, ContinueReader, FramedBody};
 use http_parse_error::HttpParseError;
 use include_dir::{include_dir, Dir};
 use logger::Logger;
 use native_tls::{Identity, TlsAcceptor};
-use std::borrow::Cow;
+use request::BodySource;
 use std::collections::HashMap;
-use std::fs::{self, File};
+use std::fs;
 use std::io::{self, BufRead, BufReader, Read, Write};
 use std::net::{IpAddr, SocketAddr, TcpListener, TcpStream};
 use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use termcolor::Color;
+use thread_pool::Backlog;
 use utils::get_option;
 
+mod body;
 mod errors;
 mod http_response;
 mod logger;
+mod multipart;
+mod request;
 mod router;
 mod thread_pool;
 mod utils;
 
 pub use errors::*;
 pub use http_response::*;
+pub use multipart::*;
+pub use request::*;
 pub use router::*;
 
 pub static STATIC_FILES: Dir<'_> = include_dir!("src/dist");
@@ -86,6 +94,10 @@ pub struct HttpServer {
     logger: Option<Arc<Logger>>,
     bind_address: IpAddr,
     compression: bool,
+    keep_alive_timeout: Duration,
+    body_timeout: Duration,
+    max_requests: usize,
+    max_body_size: Option<u64>,
 }
 
 impl HttpServer {
@@ -106,6 +118,10 @@ impl HttpServer {
             logger: None,
             bind_address,
             compression,
+            keep_alive_timeout: Duration::from_secs(5),
+            body_timeout: Duration::from_secs(60),
+            max_requests: 100,
+            max_body_size: None,
         }
     }
     pub fn with_logger(mut self) -> Self {
@@ -121,6 +137,13 @@ impl HttpServer {
         self
     }
 
+    /// Sets the directory handlers serve files from, available to them as `Request::root`.
+    /// Defaults to the current directory.
+    pub fn with_root(mut self, root: PathBuf) -> Self {
+        self.router = self.router.with_root(root);
+        self
+    }
+
     pub fn add_routes<F>(mut self, routes: F) -> Self
     where
         F: Fn(&mut Router) + Send + Sync + 'static,
@@ -133,10 +156,37 @@ impl HttpServer {
         self.router = self.router.with_cors(policy);
         self
     }
+
+    /// Sets how long an idle persistent connection is kept open and how many
+    /// requests can be served over a single connection before it is closed.
+    ///
+    /// Every open connection occupies a worker thread, so while all workers are busy and
+    /// new connections are waiting, idle connections are closed right away and responses
+    /// are sent with `Connection: close`. Clients then pay for a new connection instead of
+    /// waiting until an idle one times out.
+    pub fn with_keep_alive(mut self, timeout: Duration, max_requests: usize) -> Self {
+        self.keep_alive_timeout = timeout;
+        self.max_requests = max_requests.max(1);
+        self
+    }
+
+    /// Sets how long reading a request may stall once it has started arriving, e.g. while
+    /// a slow client uploads a body. Defaults to 60 seconds.
+    pub fn with_body_timeout(mut self, timeout: Duration) -> Self {
+        self.body_timeout = timeout;
+        self
+    }
+    /// Limits the size of request bodies. Larger bodies are rejected with `413 Payload Too Large`.
+    pub fn with_max_body_size(mut self, max_body_size: u64) -> Self {
+        self.max_body_size = Some(max_body_size);
+        self
+    }
+
     pub fn run(self) -> Result<(), Box<dyn std::error::Error>> {
         self.print_server_info();
         let listener = TcpListener::bind(SocketAddr::from((self.bind_address, self.port)))?;
         let pool = thread_pool::ThreadPool::build(self.threads)?;
+        let backlog = pool.backlog();
 
         let arc_router = Arc::new(self.router);
         let mut network_stream =
@@ -144,48 +194,96 @@ impl HttpServer {
         for stream in listener.incoming() {
             let stream = stream?;
             let peer_addr = stream.peer_addr()?;
+            stream.set_read_timeout(Some(self.keep_alive_timeout))?;
+            //timeouts are switched on the socket itself, also when it is wrapped for TLS
+            let Ok(socket) = stream.try_clone() else {
+                continue;
+            };
             let Ok(stream) = network_stream.get_stream(stream) else {
                 continue;
             };
-            let mut stream = stream.delegate.take().unwrap();
+            let stream = stream.delegate.take().unwrap();
 
             let router_clone = Arc::clone(&arc_router);
             let logger_clone = self.logger.clone();
+            let keep_alive_header = format!(
+                "timeout={}, max={}",
+                self.keep_alive_timeout.as_secs(),
+                self.max_requests
+            );
+            let max_requests = self.max_requests;
+            let max_body_size = self.max_body_size;
+            let keep_alive_timeout = self.keep_alive_timeout;
+            let body_timeout = self.body_timeout;
+            let backlog = backlog.clone();
 
             pool.execute(move || {
-                let (response, req_headers) =
-                    handle_connection(&mut stream, &router_clone, peer_addr.ip()).unwrap_or_else(
-                        |err| {
-                            if let (Some(method), Some(path)) = (&err.method, &err.path) {
-                                router_clone
-                                    .log_response(
-                                        err.error_response.status_code,
-                                        path,
-                                        method,
-                                        peer_addr.ip(),
-                                    )
-                                    .unwrap();
-                            }
-
-                            (err.into_response(), HashMap::new())
+                let mut reader = BufReader::new(stream);
+                let mut handled_requests = 0;
+
+                while let Ok(true) =
+                    await_request(&mut reader, &socket, keep_alive_timeout, &backlog)
+                {
+                    if socket.set_read_timeout(Some(body_timeout)).is_err() {
+                        break;
+                    }
+                    let exchange = match handle_connection(
+                        &mut reader,
+                        &router_clone,
+                        peer_addr.ip(),
+                        max_body_size,
+                    ) {
+                        Ok(Some(exchange)) => exchange,
+                        Ok(None) => break,
+                        Err(err) => Exchange {
+                            response: log_error_response(err, &router_clone, peer_addr.ip()),
+                            headers: HashMap::new(),
+                            keep_alive: false,
+                            supports_chunked: false,
                         },
-                    );
-
-                let compress = self.compression
-                    && req_headers.iter().any(|(key, value)| {
-                        key.eq_ignore_ascii_case("accept-encoding")
-                            && value.to_ascii_lowercase().contains("gzip")
-                    });
-
-                response
-                    .write_response(&mut stream, compress)
-                    .unwrap_or_else(|err| {
-                        if let Some(logger) = logger_clone {
+                    };
+                    handled_requests += 1;
+
+                    //streamed bodies are compressed on the fly and need chunked framing, which HTTP/1.0 lacks
+                    let response = exchange.response;
+                    let compress = self.compression
+                        && (exchange.supports_chunked
+                            || !matches!(response.body, Some(Body::FileStream(_))))
+                        && exchange.headers.iter().any(|(key, value)| {
+                            key.eq_ignore_ascii_case("accept-encoding")
+                                && value.to_ascii_lowercase().contains("gzip")
+                        });
+                    let response = if self.compression {
+                        response.with_encoding(compress)
+                    } else {
+                        response
+                    };
+
+                    let keep_alive = exchange.keep_alive
+                        && handled_requests < max_requests
+                        && !backlog.is_waiting();
+
+                    let response = if keep_alive {
+                        response
+                            .add_response_header("Connection", "keep-alive")
+                            .add_response_header("Keep-Alive", &keep_alive_header)
+                    } else {
+                        response.add_response_header("Connection", "close")
+                    };
+
+                    if let Err(err) = response.write_response(reader.get_mut(), compress) {
+                        if let Some(logger) = &logger_clone {
                             logger
                                 .log_stderr("Error: {}", vec![(err.to_string(), Some(Color::Red))])
                                 .unwrap();
                         }
-                    });
+                        break;
+                    }
+
+                    if !keep_alive {
+                        break;
+                    }
+                }
             })?;
         }
         Ok(())
@@ -214,6 +312,7 @@ HTTPS: {}
 CORS: {}
 Auth: {}
 Compression: {}
+Root: {}
 
 ====================
 Logs:"#,
@@ -224,6 +323,7 @@ Logs:"#,
                     get_option(&self.router.cors),
                     get_option(&self.router.credentials),
                     if self.compression { ("Enabled".to_string(), Some(Color::Green)) } else { ("Disabled".to_string(), Some(Color::Yellow)) },
+                    (self.router.root.display().to_string(), Some(Color::Blue)),
                 ],
             )
             .unwrap();
@@ -231,13 +331,46 @@ Logs:"#,
     }
 }
 
+/// Method, target, version and every header line of a request, duplicates included.
+type RequestHead<'a> = (&'a str, &'a str, &'a str, Vec<(&'a str, &'a str)>);
+
+struct Exchange {
+    response: HttpResponse,
+    headers: HashMap<String, String>,
+    keep_alive: bool,
+    supports_chunked: bool,
+}
+
 fn parse_http<'a>(
-    reader: &mut BufReader<&mut Box<dyn ReadWrite>>,
+    reader: &mut BufReader<Box<dyn ReadWrite>>,
     request_string: &'a mut String,
-) -> Result<(&'a str, &'a str, HashMap<&'a str, &'a str>), HttpParseError> {
+) -> Result<Option<RequestHead<'a>>, HttpParseError> {
     loop {
         let mut line = String::new();
-        reader.read_line(&mut line)?;
+        match reader.read_line(&mut line) {
+            //connection closed or idle timeout reached before a new request arrived
+            Ok(0) if request_string.is_empty() => return Ok(None),
+            Err(err)
+                if request_string.is_empty()
+                    && matches!(
+                        err.kind(),
+                        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                    ) =>
+            {
+                return Ok(None)
+            }
+            Ok(0) => {
+                return Err(HttpParseError {
+                    message: "Connection closed before request was complete".to_string(),
+                })
+            }
+            Ok(_) => {}
+            Err(err) => return Err(err.into()),
+        }
+        //tolerate empty lines preceding the request line
+        if request_string.is_empty() && line.trim().is_empty() {
+            continue;
+        }
         request_string.push_str(&line);
         if line == "\r\n" {
             break;
@@ -260,31 +393,98 @@ fn parse_http<'a>(
         return Err(HttpParseError::default());
     }
 
-    let (method, path, _version) = (http_method[0], http_method[1], http_method[2]);
+    let (method, path, version) = (http_method[0], http_method[1], http_method[2]);
 
-    let mut headers = std::collections::HashMap::new();
-    for line in &request_lines[1..] {
-        let parts: Vec<&str> = line.splitn(2, ':').collect();
-        if parts.len() == 2 {
-            headers.insert(
-                *parts.first().ok_or(HttpParseError::default())?,
-                parts.get(1).ok_or(HttpParseError::default())?.trim(),
-            );
-        }
+    let headers = request_lines[1..]
+        .iter()
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name, value.trim()))
+        .collect();
+
+    Ok(Some((method, path, version, headers)))
+}
+
+fn get_header<'a>(headers: &HashMap<&str, &'a str>, name: &str) -> Option<&'a str> {
+    headers
+        .iter()
+        .find(|(key, _)| key.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.trim())
+}
+
+fn is_keep_alive(version: &str, headers: &HashMap<&str, &str>) -> bool {
+    let connection = get_header(headers, "Connection").map(|value| value.to_ascii_lowercase());
+
+    match connection {
+        Some(value) if value.contains("close") => false,
+        Some(value) if value.contains("keep-alive") => true,
+        _ => version != "HTTP/1.0",
     }
+}
 
-    Ok((method, path, headers))
+fn log_error_response(err: ApiError, router: &Router, peer_addr: IpAddr) -> HttpResponse {
+    if let (Some(method), Some(path)) = (&err.method, &err.path) {
+        router
+            .log_response(err.error_response.status_code, path, method, peer_addr)
+            .unwrap();
+    }
+    err.into_response()
 }
 
+/// How often a connection waiting for its next request checks for connections queued for a
+/// worker.
+const IDLE_POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Waits up to `timeout` for the next request on a connection to start arriving. Returns
+/// `false` when the client closed the connection, stayed idle for `timeout`, or is idle
+/// while other connections wait for a worker.
+fn await_request(
+    reader: &mut BufReader<Box<dyn ReadWrite>>,
+    socket: &TcpStream,
+    timeout: Duration,
+    backlog: &Backlog,
+) -> io::Result<bool> {
+    let started = Instant::now();
+    loop {
+        let remaining = timeout.saturating_sub(started.elapsed());
+        if remaining.is_zero() {
+            return Ok(false);
+        }
+        socket.set_read_timeout(Some(remaining.min(IDLE_POLL_INTERVAL)))?;
+        match reader.fill_buf() {
+            Ok(available) => return Ok(!available.is_empty()),
+            Err(err)
+                if matches!(
+                    err.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) =>
+            {
+                if backlog.is_waiting() {
+                    return Ok(false);
+                }
+            }
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/// Largest unread request body remainder discarded to keep a connection open.
+const MAX_DRAINED_BODY: u64 = 256 * 1024;
+
 fn handle_connection(
-    stream: &mut Box<dyn ReadWrite>,
+    reader: &mut BufReader<Box<dyn ReadWrite>>,
     router: &Arc<Router>,
     peer_addr: IpAddr,
-) -> Result<(HttpResponse, HashMap<String, String>), ApiError> {
-    let mut reader = BufReader::new(&mut *stream);
-
+    max_body_size: Option<u64>,
+) -> Result<Option<Exchange>, ApiError> {
     let mut request = String::new();
-    let (method, path, headers) = parse_http(&mut reader, &mut request)?;
+    let Some((method, path, version, header_lines)) = parse_http(reader, &mut request)? else {
+        return Ok(None);
+    };
+    //framing is decided on every header line, a later duplicate must not override an earlier one
+    let body_length = get_body_length(&header_lines)?;
+    let headers: HashMap<&str, &str> = header_lines.into_iter().collect();
+    let mut keep_alive = is_keep_alive(version, &headers);
+    let supports_chunked = version != "HTTP/1.0";
 
     //find a better way without converting to owned headers
     let owned_headers: HashMap<String, String> = headers
@@ -292,111 +492,50 @@ fn handle_connection(
         .map(|(k, v)| (k.to_string(), v.to_string()))
         .collect();
 
-    let mut buffer = Vec::new();
-
-    let body = match headers.get("Content-Type") {
-        Some(content_type) if content_type.contains("multipart/form-data") => {
-            let path = headers.get("Path").unwrap();
-            let response = handle_multipart_file_upload(content_type, &headers, &mut reader, path)
-                .map_err(|err| {
-                    ApiError::new_with_html(400, &format!("File upload error: {}", err))
-                })?;
-            return Ok((response, owned_headers));
-        }
-        _ => parse_body(&headers, reader, &mut buffer)?,
-    };
-
-    let response = router.route(path, method, body.as_deref(), peer_addr, &headers)?;
-
-    Ok((response, owned_headers))
-}
-
-fn parse_body<'a>(
-    headers: &HashMap<&str, &str>,
-    reader: BufReader<&mut Box<dyn ReadWrite>>,
-    buffer: &'a mut Vec<u8>,
-) -> Result<Option<Cow<'a, str>>, Box<dyn std::error::Error>> {
-    match headers.get("Content-Length") {
-        Some(content_length) => {
-            let content_length = content_length.parse::<usize>()?;
-            let mut body_reader = reader.take(content_length.try_into()?);
-            body_reader.read_to_end(buffer)?;
-            let body = String::from_utf8_lossy(&buffer[..]);
-            Ok(Some(body))
+    //bodies announced as too large are rejected before reading, the connection is closed after the response
+    if let (Some(BodyLength::Fixed(length)), Some(limit)) = (&body_length, max_body_size) {
+        if *length > limit {
+            return Err(ApiError::from(io::Error::other(BodyTooLarge { limit })));
         }
-        None => Ok(None),
     }
-}
 
-fn handle_multipart_file_upload(
-    content_type: &str,
-    headers: &HashMap<&str, &str>,
-    reader: &mut BufReader<&mut Box<dyn ReadWrite>>,
-    path: &str,
-) -> Result<HttpResponse, Box<dyn std::error::Error>> {
-    let idx = content_type
-        .find("boundary=")
-        .ok_or("Missing multipart boundary")?;
-    let boundary = format!("--{}", &content_type[(idx + "boundary=".len())..]);
-    let mut multipart_headers = HashMap::new();
-    let mut header_size = 0;
-
-    //read headers
-    loop {
-        let mut line = String::new();
-        header_size += reader.read_line(&mut line)?;
-        if line.trim() == boundary {
-            continue;
-        }
-        if line == "\r\n" {
-            break;
-        }
-
-        let parts: Vec<&str> = line.trim().split(':').map(|s| s.trim()).collect();
-        if parts.len() < 2 {
-            return Err("Error parsing multipart request".into());
-        }
-        multipart_headers.insert(parts[0].to_owned(), parts[1].to_owned());
+    //clients such as curl hold back large bodies until they get an interim response, which
+    //is only sent once the handler reads the body
+    let expects_continue = supports_chunked
+        && get_header(&headers, "Expect")
+            .is_some_and(|expect| expect.eq_ignore_ascii_case("100-continue"));
+    let mut body = body_length.map(|length| {
+        FramedBody::new(
+            ContinueReader::new(&mut *reader, expects_continue),
+            length,
+            max_body_size,
+        )
+    });
+
+    let request_body = body
+        .as_mut()
+        .map(|body| RequestBody::new(body as &mut dyn BodySource));
+    let response = router
+        .route(path, method, request_body, peer_addr, &headers)
+        .unwrap_or_else(|err| log_error_response(err, router, peer_addr));
+
+    //skip a small remainder the handler did not read. A large one, or a body the client is
+    //still holding back, is not worth receiving, so the connection is closed instead
+    if let Some(body) = &mut body {
+        keep_alive =
+            keep_alive && !body.awaits_continue() && body.drain(MAX_DRAINED_BODY).unwrap_or(false);
     }
 
-    //get file name from content disposition and form target path
-    let content_disposition = multipart_headers
-        .get("Content-Disposition")
-        .ok_or("Missing content disposition")?;
-    let filename = content_disposition
-        .split("filename=\"")
-        .nth(1)
-        .and_then(|s| s.split('\"').next())
-        .ok_or("Error parsing file name")?;
-    let mut target_path = PathBuf::from("./").canonicalize()?.join(path);
-    target_path.push(filename);
-
-    let current_dir = std::env::current_dir()?;
-    if !target_path.starts_with(current_dir) {
-        return Err("Only paths relative to the current directory are allowed".into());
-    }
+    let response = if method == HttpMethod::HEAD.as_str() {
+        response.into_head_response()
+    } else {
+        response
+    };
 
-    //calculate file size based on whole content length so that reading the stream can be stopped
-    let mut file = File::create(target_path)?;
-    let content_length = headers
-        .get("Content-Length")
-        .ok_or("Missing content length")?
-        .parse::<usize>()?;
-    let file_bytes = content_length - boundary.len() - header_size - 6;
-
-    //take only the file length from the main buf reader
-    let mut limited_reader = reader.take(file_bytes.try_into()?);
-
-    //copy streams
-    io::copy(&mut limited_reader, &mut file)?;
-
-    let response = HttpResponse::new(
-        Some(Body::Text(format!(
-            "File {} uploaded successfully.",
-            filename
-        ))),
-        Some(String::from("text/plain")),
-        200,
-    );
-    Ok(response)
+    Ok(Some(Exchange {
+        response,
+        headers: owned_headers,
+        keep_alive,
+        supports_chunked,
+    }))
 }
@@ -6,9 +6,16 @@ fn main() {
         server,
         authorize,
         index_path,
+        upload_config,
+        mounts,
     } = build_server();
     server
-        .add_routes(api::create_routes(authorize, index_path))
+        .add_routes(api::create_routes(
+            authorize,
+            index_path,
+            upload_config,
+            mounts,
+        ))
         .run()
         .expect("Starting server failed");
 }
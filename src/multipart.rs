@@ -0,0 +1,363 @@
+use std::{
+    fmt,
+    io::{self, Read},
+};
+
+use crate::api_error::ApiError;
+
+const READ_SIZE: usize = 8 * 1024;
+const MAX_HEADER_SIZE: usize = 16 * 1024;
+
+/// Streaming reader for `multipart/form-data` bodies.
+///
+/// Parts are yielded one at a time by `next_part`; each part's body is read straight from
+/// the underlying stream, so only the bytes needed to recognize a boundary are buffered.
+/// Unread part bodies are skipped when the next part is requested.
+pub struct Multipart<R: Read> {
+    reader: R,
+    delimiter: Vec<u8>,
+    buf: Vec<u8>,
+    pos: usize,
+    state: State,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum State {
+    Body,
+    Delimiter,
+    Finished,
+}
+
+impl<R: Read> Multipart<R> {
+    pub fn new(reader: R, boundary: &str) -> Self {
+        Multipart {
+            reader,
+            delimiter: format!("\r\n--{}", boundary).into_bytes(),
+            //the first boundary may open the body without a preceding line break
+            buf: b"\r\n".to_vec(),
+            pos: 0,
+            state: State::Body,
+        }
+    }
+
+    /// Creates a reader using the boundary of a `multipart/*` `Content-Type` header value,
+    /// failing with `400` when the header has none.
+    pub fn from_content_type(content_type: &str, reader: R) -> Result<Self, ApiError> {
+        let boundary = parse_boundary(content_type)
+            .ok_or_else(|| ApiError::new_with_json(400, "Missing multipart boundary"))?;
+        Ok(Multipart::new(reader, &boundary))
+    }
+
+    /// Advances to the next part, skipping whatever is left of the current one. Returns
+    /// `None` after the closing boundary.
+    pub fn next_part(&mut self) -> Result<Option<Part<'_, R>>, ApiError> {
+        let mut skipped = [0; READ_SIZE];
+        while self.read_body(&mut skipped)? > 0 {}
+        if self.state == State::Finished {
+            return Ok(None);
+        }
+
+        self.ensure(self.delimiter.len() + 2)?;
+        self.pos += self.delimiter.len();
+        if self.available().starts_with(b"--") {
+            self.state = State::Finished;
+            return Ok(None);
+        }
+
+        //transport padding may follow the boundary before the line break
+        let line = self.read_line()?;
+        if !line
+            .iter()
+            .all(|b| matches!(b, b' ' | b'\t' | b'\r' | b'\n'))
+        {
+            return Err(malformed("Invalid multipart boundary line").into());
+        }
+
+        let headers = self.read_headers()?;
+        let disposition = headers
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case("Content-Disposition"))
+            .map(|(_, value)| parse_header_params(value))
+            .unwrap_or_default();
+        self.state = State::Body;
+
+        Ok(Some(Part {
+            name: disposition_param(&disposition, "name"),
+            file_name: disposition_param(&disposition, "filename"),
+            headers,
+            multipart: self,
+        }))
+    }
+
+    fn available(&self) -> &[u8] {
+        &self.buf[self.pos..]
+    }
+
+    /// Reads more input into the buffer, returning `false` at the end of the stream.
+    fn fill(&mut self) -> io::Result<bool> {
+        self.buf.drain(..self.pos);
+        self.pos = 0;
+        let len = self.buf.len();
+        self.buf.resize(len + READ_SIZE, 0);
+        loop {
+            match self.reader.read(&mut self.buf[len..]) {
+                Ok(read) => {
+                    self.buf.truncate(len + read);
+                    return Ok(read > 0);
+                }
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => {
+                    self.buf.truncate(len);
+                    return Err(err);
+                }
+            }
+        }
+    }
+
+    fn ensure(&mut self, len: usize) -> io::Result<()> {
+        while self.available().len() < len {
+            if !self.fill()? {
+                return Err(malformed("Multipart body ended unexpectedly"));
+            }
+        }
+        Ok(())
+    }
+
+    fn read_line(&mut self) -> io::Result<Vec<u8>> {
+        loop {
+            if let Some(end) = self.available().iter().position(|b| *b == b'\n') {
+                let line = self.available()[..=end].to_vec();
+                self.pos += end + 1;
+                return Ok(line);
+            }
+            if self.available().len() > MAX_HEADER_SIZE {
+                return Err(malformed("Multipart part headers are too large"));
+            }
+            if !self.fill()? {
+                return Err(malformed("Multipart body ended unexpectedly"));
+            }
+        }
+    }
+
+    fn read_headers(&mut self) -> io::Result<Vec<(String, String)>> {
+        let mut headers: Vec<(String, String)> = Vec::new();
+        let mut size = 0;
+        loop {
+            let line = self.read_line()?;
+            size += line.len();
+            if size > MAX_HEADER_SIZE {
+                return Err(malformed("Multipart part headers are too large"));
+            }
+            let line = String::from_utf8_lossy(&line);
+            let line = line.trim_end_matches(['\r', '\n']);
+            if line.is_empty() {
+                return Ok(headers);
+            }
+            //folded continuation of the previous header
+            if line.starts_with([' ', '\t']) {
+                if let Some((_, value)) = headers.last_mut() {
+                    value.push(' ');
+                    value.push_str(line.trim());
+                    continue;
+                }
+            }
+            let (name, value) = line
+                .split_once(':')
+                .ok_or_else(|| malformed("Invalid multipart part header"))?;
+            headers.push((name.trim().to_string(), value.trim().to_string()));
+        }
+    }
+
+    /// Reads from the current part's body, stopping at the next delimiter.
+    fn read_body(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        if self.state != State::Body || out.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            let available = self.available();
+            let readable = match find(available, &self.delimiter) {
+                Some(0) => {
+                    self.state = State::Delimiter;
+                    return Ok(0);
+                }
+                Some(idx) => idx,
+                //keep back anything that could be the start of a delimiter
+                None => available.len().saturating_sub(self.delimiter.len() - 1),
+            };
+            if readable > 0 {
+                let len = readable.min(out.len());
+                out[..len].copy_from_slice(&available[..len]);
+                self.pos += len;
+                return Ok(len);
+            }
+            if !self.fill()? {
+                return Err(malformed(
+                    "Multipart body ended before the closing boundary",
+                ));
+            }
+        }
+    }
+}
+
+/// A single part of a multipart body. Reading from it yields the part's content.
+pub struct Part<'m, R: Read> {
+    name: Option<String>,
+    file_name: Option<String>,
+    headers: Vec<(String, String)>,
+    multipart: &'m mut Multipart<R>,
+}
+
+impl<R: Read> Part<'_, R> {
+    /// Form field name from the `Content-Disposition` header.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// File name from the `Content-Disposition` header, preferring the RFC 5987 encoded
+    /// `filename*` parameter when present.
+    pub fn file_name(&self) -> Option<&str> {
+        self.file_name.as_deref()
+    }
+
+    pub fn content_type(&self) -> Option<&str> {
+        self.header("Content-Type")
+    }
+
+    /// Looks up a part header by name, ignoring ASCII case.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn headers(&self) -> &[(String, String)] {
+        &self.headers
+    }
+}
+
+impl<R: Read> Read for Part<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.multipart.read_body(buf)
+    }
+}
+
+/// Error raised while reading a multipart body that does not follow the expected format.
+#[derive(Debug)]
+pub struct MalformedMultipart {
+    message: &'static str,
+}
+
+impl fmt::Display for MalformedMultipart {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for MalformedMultipart {}
+
+fn malformed(message: &'static str) -> io::Error {
+    io::Error::other(MalformedMultipart { message })
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+/// Extracts the `boundary` parameter of a `Content-Type` header value.
+pub fn parse_boundary(content_type: &str) -> Option<String> {
+    parse_header_params(content_type)
+        .into_iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case("boundary"))
+        .map(|(_, value)| value)
+        .filter(|boundary| !boundary.is_empty())
+}
+
+/// Splits a header value like `form-data; name="a;b"; filename*=UTF-8''x` into its
+/// parameters, unquoting quoted strings. The leading value is skipped.
+fn parse_header_params(value: &str) -> Vec<(String, String)> {
+    let mut params = Vec::new();
+    let mut chars = value.chars().peekable();
+
+    //skip the main value
+    for c in chars.by_ref() {
+        if c == ';' {
+            break;
+        }
+    }
+
+    loop {
+        let mut name = String::new();
+        for c in chars.by_ref() {
+            if c == '=' || c == ';' {
+                break;
+            }
+            name.push(c);
+        }
+        let name = name.trim().to_string();
+
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        let mut param_value = String::new();
+        if chars.next_if_eq(&'"').is_some() {
+            while let Some(c) = chars.next() {
+                match c {
+                    '"' => break,
+                    '\\' => param_value.extend(chars.next()),
+                    c => param_value.push(c),
+                }
+            }
+            for c in chars.by_ref() {
+                if c == ';' {
+                    break;
+                }
+            }
+        } else {
+            for c in chars.by_ref() {
+                if c == ';' {
+                    break;
+                }
+                param_value.push(c);
+            }
+            param_value = param_value.trim().to_string();
+        }
+
+        if !name.is_empty() {
+            params.push((name, param_value));
+        }
+        if chars.peek().is_none() {
+            return params;
+        }
+    }
+}
+
+/// Returns a disposition parameter, preferring its RFC 5987 `name*` form when it decodes.
+fn disposition_param(params: &[(String, String)], name: &str) -> Option<String> {
+    let extended = format!("{}*", name);
+    params
+        .iter()
+        .find(|(key, _)| key.eq_ignore_ascii_case(&extended))
+        .and_then(|(_, value)| decode_ext_value(value))
+        .or_else(|| {
+            params
+                .iter()
+                .find(|(key, _)| key.eq_ignore_ascii_case(name))
+                .map(|(_, value)| value.clone())
+        })
+}
+
+/// Decodes an RFC 5987 `charset'language'value` string. Only UTF-8 and ISO-8859-1 are supported.
+fn decode_ext_value(value: &str) -> Option<String> {
+    let mut parts = value.splitn(3, '\'');
+    let charset = parts.next()?;
+    let _language = parts.next()?;
+    let bytes: Vec<u8> = percent_encoding::percent_decode_str(parts.next()?).collect();
+    if charset.eq_ignore_ascii_case("UTF-8") {
+        String::from_utf8(bytes).ok()
+    } else if charset.eq_ignore_ascii_case("ISO-8859-1") {
+        Some(bytes.into_iter().map(char::from).collect())
+    } else {
+        None
+    }
+}
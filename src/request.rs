@@ -0,0 +1,221 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    io::{self, BufRead, Read},
+    net::IpAddr,
+    path::Path,
+};
+
+use crate::api_error::ApiError;
+
+/// A parsed HTTP request as seen by route handlers registered with `Router::add_handler`.
+///
+/// Query values and path parameters are passed through as they appear in the request
+/// target, without percent-decoding.
+pub struct Request<'a> {
+    method: &'a str,
+    path: &'a str,
+    query: HashMap<&'a str, &'a str>,
+    params: HashMap<&'a str, &'a str>,
+    headers: &'a HashMap<&'a str, &'a str>,
+    peer_addr: IpAddr,
+    user: Option<&'a str>,
+    root: &'a Path,
+    body: Option<RequestBody<'a>>,
+}
+
+impl<'a> Request<'a> {
+    pub(crate) fn new(
+        method: &'a str,
+        target: &'a str,
+        headers: &'a HashMap<&'a str, &'a str>,
+        peer_addr: IpAddr,
+        body: Option<RequestBody<'a>>,
+    ) -> Self {
+        let (path, query) = match target.split_once('?') {
+            Some((path, query)) => (path, parse_query(query)),
+            None => (target, HashMap::new()),
+        };
+        Request {
+            method,
+            path,
+            query,
+            params: HashMap::new(),
+            headers,
+            peer_addr,
+            user: None,
+            root: Path::new("."),
+            body,
+        }
+    }
+
+    pub(crate) fn with_params(mut self, params: HashMap<&'a str, &'a str>) -> Self {
+        self.params = params;
+        self
+    }
+
+    pub(crate) fn with_user(mut self, user: Option<&'a str>) -> Self {
+        self.user = user;
+        self
+    }
+
+    pub(crate) fn with_root(mut self, root: &'a Path) -> Self {
+        self.root = root;
+        self
+    }
+
+    pub fn method(&self) -> &'a str {
+        self.method
+    }
+
+    /// Request path without the query string.
+    pub fn path(&self) -> &'a str {
+        self.path
+    }
+
+    pub fn query(&self, name: &str) -> Option<&'a str> {
+        self.query.get(name).copied()
+    }
+
+    pub fn query_params(&self) -> &HashMap<&'a str, &'a str> {
+        &self.query
+    }
+
+    /// Value captured by a `{name}` segment of the route pattern.
+    pub fn param(&self, name: &str) -> Option<&'a str> {
+        self.params.get(name).copied()
+    }
+
+    pub fn params(&self) -> &HashMap<&'a str, &'a str> {
+        &self.params
+    }
+
+    /// Looks up a header by name, ignoring ASCII case.
+    pub fn header(&self, name: &str) -> Option<&'a str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| *value)
+    }
+
+    pub fn headers(&self) -> &HashMap<&'a str, &'a str> {
+        self.headers
+    }
+
+    pub fn cookie(&self, name: &str) -> Option<&'a str> {
+        self.header("Cookie")?
+            .split(';')
+            .filter_map(|cookie| cookie.trim().split_once('='))
+            .find(|(key, _)| *key == name)
+            .map(|(_, value)| value.trim_matches('"'))
+    }
+
+    pub fn peer_addr(&self) -> IpAddr {
+        self.peer_addr
+    }
+
+    /// Username that passed Basic Auth, set only for routes registered with `authorize`.
+    pub fn user(&self) -> Option<&'a str> {
+        self.user
+    }
+
+    /// Directory files are served from, set with `HttpServer::with_root`. Defaults to the
+    /// current directory.
+    pub fn root(&self) -> &'a Path {
+        self.root
+    }
+
+    pub fn has_body(&self) -> bool {
+        self.body.is_some()
+    }
+
+    /// Streaming access to the request body. A client sending `Expect: 100-continue` is told
+    /// to go ahead on the first read. A small remainder the handler leaves unread is
+    /// discarded after the response is built, a larger one closes the connection.
+    pub fn body(&mut self) -> Option<&mut RequestBody<'a>> {
+        self.body.as_mut()
+    }
+
+    /// Reads the whole request body into memory. Fails with `413` when the body is larger
+    /// than the server's maximum body size.
+    pub fn read_body(&mut self) -> Result<Vec<u8>, ApiError> {
+        let mut buffer = Vec::new();
+        if let Some(body) = &mut self.body {
+            body.read_to_end(&mut buffer)?;
+        }
+        Ok(buffer)
+    }
+
+    /// Reads the whole request body as UTF-8 text, failing with `400` on invalid input.
+    pub fn read_text(&mut self) -> Result<String, ApiError> {
+        String::from_utf8(self.read_body()?)
+            .map_err(|_| ApiError::new_with_json(400, "Request body is not valid UTF-8"))
+    }
+
+    /// Path parameters merged with query parameters, as passed to `Router::add_route` handlers.
+    pub(crate) fn merged_params(&self) -> HashMap<&'a str, &'a str> {
+        let mut params = self.params.clone();
+        params.extend(self.query.iter().map(|(key, value)| (*key, *value)));
+        params
+    }
+}
+
+fn parse_query(query: &str) -> HashMap<&str, &str> {
+    query
+        .split('&')
+        .filter_map(|param| {
+            let pair: Vec<&str> = param.split('=').collect();
+            (pair.len() == 2).then(|| (pair[0], pair[1]))
+        })
+        .collect()
+}
+
+pub(crate) trait BodySource: BufRead {
+    fn trailers(&self) -> &[(String, String)];
+}
+
+/// Request body stream, bounded by the maximum body size configured on the server.
+pub struct RequestBody<'a> {
+    source: &'a mut (dyn BodySource + 'a),
+}
+
+impl<'a> RequestBody<'a> {
+    pub(crate) fn new(source: &'a mut (dyn BodySource + 'a)) -> Self {
+        RequestBody { source }
+    }
+
+    /// Trailer fields sent after a chunked body, available once the body has been read.
+    pub fn trailers(&self) -> &[(String, String)] {
+        self.source.trailers()
+    }
+}
+
+impl Read for RequestBody<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.source.read(buf)
+    }
+}
+
+impl BufRead for RequestBody<'_> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.source.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.source.consume(amt)
+    }
+}
+
+/// Error raised while reading a request body that exceeds the configured maximum size.
+#[derive(Debug)]
+pub struct BodyTooLarge {
+    pub limit: u64,
+}
+
+impl fmt::Display for BodyTooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Request body exceeds the limit of {} bytes", self.limit)
+    }
+}
+
+impl std::error::Error for BodyTooLarge {}
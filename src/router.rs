@@ -1,12 +1,12 @@
 use base64::{prelude::BASE64_STANDARD, Engine};
-use regex::Regex;
+use regex::{Captures, Regex};
 use serde_json::json;
-use std::{collections::HashMap, net::IpAddr, sync::Arc};
+use std::{collections::HashMap, net::IpAddr, path::PathBuf, sync::Arc};
 use termcolor::Color;
 
-use crate::{logger::Logger, ApiError, Body, HttpResponse};
+use crate::{get_header, logger::Logger, ApiError, Body, HttpResponse, Request, RequestBody};
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub enum HttpMethod {
     GET,
     POST,
@@ -25,7 +25,7 @@ pub struct Credentials {
 }
 
 impl HttpMethod {
-    fn as_str(&self) -> &str {
+    pub fn as_str(&self) -> &str {
         match self {
             HttpMethod::GET => "GET",
             HttpMethod::POST => "POST",
@@ -40,6 +40,27 @@ impl HttpMethod {
     }
 }
 
+/// Builds the `Allow` header value from the routes registered for a path. HEAD is implied
+/// by GET and OPTIONS is always answered by the router.
+fn allowed_methods(routes: &[(&Route, Captures)]) -> String {
+    let mut methods: Vec<&str> = Vec::new();
+    for (route, _) in routes {
+        let mut route_methods = vec![route.method.as_str()];
+        if route.method == HttpMethod::GET {
+            route_methods.push(HttpMethod::HEAD.as_str());
+        }
+        for method in route_methods {
+            if !methods.contains(&method) {
+                methods.push(method);
+            }
+        }
+    }
+    if !methods.contains(&HttpMethod::OPTIONS.as_str()) {
+        methods.push(HttpMethod::OPTIONS.as_str());
+    }
+    methods.join(", ")
+}
+
 fn get_status_code_color(status_code: u16) -> Color {
     match status_code {
         100..=199 => Color::Cyan,
@@ -50,8 +71,7 @@ fn get_status_code_color(status_code: u16) -> Color {
     }
 }
 
-type Handler =
-    Box<dyn Fn(Option<&str>, HashMap<&str, &str>) -> Result<HttpResponse, ApiError> + Send + Sync>;
+type Handler = Box<dyn Fn(Request) -> Result<HttpResponse, ApiError> + Send + Sync>;
 
 pub struct Route {
     pattern: Regex,
@@ -64,6 +84,7 @@ pub struct Router {
     logger: Option<Arc<Logger>>,
     pub(super) cors: Option<Cors>,
     pub(super) credentials: Option<Credentials>,
+    pub(super) root: PathBuf,
 }
 
 impl Router {
@@ -73,6 +94,7 @@ impl Router {
             logger: None,
             cors: None,
             credentials: None,
+            root: PathBuf::from("."),
         }
     }
     pub fn with_logger(mut self, logger: Option<Arc<Logger>>) -> Self {
@@ -85,6 +107,11 @@ impl Router {
         self
     }
 
+    pub fn with_root(mut self, root: PathBuf) -> Self {
+        self.root = root;
+        self
+    }
+
     pub fn with_credentials(mut self, password: &str, username: &str) -> Self {
         self.credentials = Some(Credentials {
             username: username.to_string(),
@@ -93,12 +120,32 @@ impl Router {
         self
     }
 
+    /// Registers a handler that receives the request body as text and a map of path and
+    /// query parameters. Use `add_handler` to access the full `Request`.
     pub fn add_route<F>(&mut self, path: &str, method: HttpMethod, handler: F, authorize: bool)
     where
         F: Fn(Option<&str>, HashMap<&str, &str>) -> Result<HttpResponse, ApiError>
             + Send
             + Sync
             + 'static,
+    {
+        self.add_handler(
+            path,
+            method,
+            move |mut request| {
+                let body = match request.has_body() {
+                    true => Some(String::from_utf8_lossy(&request.read_body()?).into_owned()),
+                    false => None,
+                };
+                handler(body.as_deref(), request.merged_params())
+            },
+            authorize,
+        );
+    }
+
+    pub fn add_handler<F>(&mut self, path: &str, method: HttpMethod, handler: F, authorize: bool)
+    where
+        F: Fn(Request) -> Result<HttpResponse, ApiError> + Send + Sync + 'static,
     {
         let pattern = if path == "/*" {
             "^(?P<wildcard>.*)$".to_string()
@@ -114,112 +161,120 @@ impl Router {
         });
     }
 
-    pub fn route(
-        &self,
-        path: &str,
-        method: &str,
-        data: Option<&str>,
+    pub fn route<'a>(
+        &'a self,
+        path: &'a str,
+        method: &'a str,
+        body: Option<RequestBody<'a>>,
         peer_addr: IpAddr,
-        headers: &HashMap<&str, &str>,
+        headers: &'a HashMap<&'a str, &'a str>,
     ) -> Result<HttpResponse, ApiError> {
-        let stripped_path: Vec<&str> = path.splitn(2, '?').collect();
-        if method == HttpMethod::OPTIONS.as_str() {
-            let mut response = HttpResponse::new(None, None, 204);
-            if let Some(cors) = &self.cors {
-                for (key, value) in &cors.headers {
-                    response = response.add_response_header(key, value);
-                }
-            }
-            Ok(response)
-        } else {
-            for route in &self.routes {
-                let pattern_match = route.pattern.captures(stripped_path[0]);
+        let request = Request::new(method, path, headers, peer_addr, body);
+        let stripped_path = request.path();
 
-                match pattern_match {
-                    Some(pattern_match) => {
-                        if route.method.as_str() != method {
-                            return Err(ApiError::new_with_json(405, "Method Not Allowed"));
-                        }
-                        if route.authorize {
-                            if let Some(credentials) = &self.credentials {
-                                if let Some(auth_header) = headers.get("Authorization") {
-                                    challenge_basic_auth(
-                                        auth_header,
-                                        &credentials.password,
-                                        &credentials.username,
-                                    )?;
-                                } else {
-                                    return Ok(HttpResponse::new(
-                                        Some(Body::Json(json!({"message": "Unauthorized"}))),
-                                        None,
-                                        401,
-                                    )
-                                    .add_response_header("WWW-Authenticate", "Basic"));
-                                }
-                            } else {
-                                return Err(ApiError::new_with_json(
-                                    500,
-                                    "Missing credentials configuration",
-                                ));
-                            }
-                        }
-                        let mut param_dict: HashMap<&str, &str> = route
-                            .pattern
-                            .capture_names()
-                            .flatten()
-                            .filter_map(|n| Some((n, pattern_match.name(n)?.as_str())))
-                            .collect();
-
-                        if stripped_path.len() == 2 {
-                            for param in stripped_path[1].split('&') {
-                                let pair: Vec<&str> = param.split('=').collect();
-                                if pair.len() == 2 {
-                                    param_dict.insert(pair[0], pair[1]);
-                                }
-                            }
-                        }
-                        let mut response =
-                            (route.handler)(data, param_dict).map_err(|mut err| {
-                                err.method = Some(method.to_string());
-                                err.path = Some(stripped_path[0].to_string());
-                                err
-                            })?;
-
-                        if let Some(cors) = &self.cors {
-                            for (key, value) in &cors.headers {
-                                response = response.add_response_header(key, value);
-                            }
-                        }
+        let matching_routes: Vec<(&Route, Captures)> = self
+            .routes
+            .iter()
+            .filter_map(|route| Some((route, route.pattern.captures(stripped_path)?)))
+            .collect();
 
-                        self.log_response(
-                            response.status_code,
-                            stripped_path[0],
-                            method,
-                            peer_addr,
-                        )?;
+        //an exact method match wins, HEAD falls back to GET and drops the body when writing the response
+        let selected_route = matching_routes
+            .iter()
+            .find(|(route, _)| route.method.as_str() == method)
+            .or_else(|| {
+                matching_routes.iter().find(|(route, _)| {
+                    method == HttpMethod::HEAD.as_str() && route.method == HttpMethod::GET
+                })
+            });
 
-                        return Ok(response);
+        let Some((route, pattern_match)) = selected_route else {
+            let response = if matching_routes.is_empty() {
+                HttpResponse::new(
+                    Some(Body::Json(
+                        json!({"message": format!("No route found for path {}", path)}),
+                    )),
+                    None,
+                    404,
+                )
+            } else {
+                let allow = allowed_methods(&matching_routes);
+                if method == HttpMethod::OPTIONS.as_str() {
+                    let mut response =
+                        HttpResponse::new(None, None, 204).add_response_header("Allow", &allow);
+                    if let Some(cors) = &self.cors {
+                        for (key, value) in &cors.headers {
+                            response = response.add_response_header(key, value);
+                        }
                     }
-                    None => continue,
+                    response
+                } else {
+                    HttpResponse::new(
+                        Some(Body::Json(json!({"message": "Method Not Allowed"}))),
+                        None,
+                        405,
+                    )
+                    .add_response_header("Allow", &allow)
+                }
+            };
+
+            self.log_response(response.status_code, stripped_path, method, peer_addr)?;
+
+            return Ok(response);
+        };
+
+        let mut user = None;
+        if route.authorize {
+            if let Some(credentials) = &self.credentials {
+                if let Some(auth_header) = headers.get("Authorization") {
+                    challenge_basic_auth(
+                        auth_header,
+                        &credentials.password,
+                        &credentials.username,
+                    )?;
+                    user = Some(credentials.username.as_str());
+                } else {
+                    return Ok(HttpResponse::new(
+                        Some(Body::Json(json!({"message": "Unauthorized"}))),
+                        None,
+                        401,
+                    )
+                    .add_response_header("WWW-Authenticate", "Basic"));
                 }
+            } else {
+                return Err(ApiError::new_with_json(
+                    500,
+                    "Missing credentials configuration",
+                ));
             }
-            let error_response = HttpResponse::new(
-                Some(Body::Json(
-                    json!({"message": format!("No route found for path {}", path)}),
-                )),
-                None,
-                404,
-            );
-
-            self.log_response(
-                error_response.status_code,
-                stripped_path[0],
-                method,
-                peer_addr,
-            )?;
-
-            Ok(error_response)
         }
+        let param_dict: HashMap<&str, &str> = route
+            .pattern
+            .capture_names()
+            .flatten()
+            .filter_map(|n| Some((n, pattern_match.name(n)?.as_str())))
+            .collect();
+
+        let request = request
+            .with_params(param_dict)
+            .with_user(user)
+            .with_root(&self.root);
+        let response = (route.handler)(request).map_err(|mut err| {
+            err.method = Some(method.to_string());
+            err.path = Some(stripped_path.to_string());
+            err
+        })?;
+        let mut response = apply_conditions(response, method, headers);
+
+        if let Some(cors) = &self.cors {
+            for (key, value) in &cors.headers {
+                response = response.add_response_header(key, value);
+            }
+        }
+
+        self.log_response(response.status_code, stripped_path, method, peer_addr)?;
+
+        Ok(response)
     }
     pub fn log_response(
         &self,
@@ -248,6 +303,25 @@ impl Router {
     }
 }
 
+/// Answers conditional and range GET and HEAD requests, before the response status is logged.
+fn apply_conditions(
+    response: HttpResponse,
+    method: &str,
+    headers: &HashMap<&str, &str>,
+) -> HttpResponse {
+    if method != HttpMethod::GET.as_str() && method != HttpMethod::HEAD.as_str() {
+        return response;
+    }
+    let response = response.with_validators(
+        get_header(headers, "If-None-Match"),
+        get_header(headers, "If-Modified-Since"),
+    );
+    match get_header(headers, "Range") {
+        Some(range) => response.with_range(range, get_header(headers, "If-Range")),
+        None => response,
+    }
+}
+
 impl Default for Router {
     fn default() -> Self {
         Self::new()
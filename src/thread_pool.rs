@@ -1,6 +1,9 @@
 use std::{
     fmt,
-    sync::{mpsc, Arc, Mutex},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc, Arc, Mutex,
+    },
     thread,
 };
 
@@ -21,6 +24,18 @@ impl std::error::Error for PoolCreationError {}
 pub struct ThreadPool {
     workers: Vec<Worker>,
     sender: Option<mpsc::Sender<Job>>,
+    backlog: Backlog,
+}
+
+/// Number of jobs submitted to a pool that no worker has picked up yet.
+#[derive(Clone, Default)]
+pub struct Backlog(Arc<AtomicUsize>);
+
+impl Backlog {
+    /// Whether jobs are waiting because every worker is busy.
+    pub fn is_waiting(&self) -> bool {
+        self.0.load(Ordering::Relaxed) > 0
+    }
 }
 
 impl ThreadPool {
@@ -32,23 +47,34 @@ impl ThreadPool {
 
         let (sender, receiver) = mpsc::channel();
         let receiver = Arc::new(Mutex::new(receiver));
+        let backlog = Backlog::default();
 
         for thread_id in 0..size {
-            workers.push(Worker::new(thread_id, Arc::clone(&receiver)));
+            workers.push(Worker::new(
+                thread_id,
+                Arc::clone(&receiver),
+                backlog.clone(),
+            ));
         }
 
         Ok(ThreadPool {
             workers,
             sender: Some(sender),
+            backlog,
         })
     }
 
+    pub fn backlog(&self) -> Backlog {
+        self.backlog.clone()
+    }
+
     pub fn execute<F>(&self, f: F) -> Result<(), mpsc::SendError<Job>>
     where
         F: FnOnce() + Send + 'static,
     {
         let job = Box::new(f);
 
+        self.backlog.0.fetch_add(1, Ordering::Relaxed);
         self.sender.as_ref().unwrap().send(job)?;
         Ok(())
     }
@@ -74,12 +100,13 @@ struct Worker {
 }
 
 impl Worker {
-    pub fn new(id: usize, receiver: Arc<Mutex<mpsc::Receiver<Job>>>) -> Worker {
+    pub fn new(id: usize, receiver: Arc<Mutex<mpsc::Receiver<Job>>>, backlog: Backlog) -> Worker {
         let thread = thread::spawn(move || loop {
             let message = receiver.lock().unwrap().recv();
 
             match message {
                 Ok(job) => {
+                    backlog.0.fetch_sub(1, Ordering::Relaxed);
                     job();
                 }
                 Err(_) => {
@@ -1,12 +1,14 @@
 use std::{
-    net::TcpListener,
-    path::PathBuf,
+    io::{BufRead, BufReader, Read, Write},
+    net::{IpAddr, TcpListener, TcpStream},
+    path::{Path, PathBuf},
     process::{Child, Command, Stdio},
     thread,
     time::Duration,
 };
 
 use reqwest::blocking::{Client, ClientBuilder};
+use scratch_server::{HttpServer, Router};
 
 pub struct TestServer {
     pub child: Child,
@@ -23,6 +25,16 @@ impl TestServer {
     }
 }
 
+pub struct LibraryServer {
+    pub port: u16,
+}
+
+impl LibraryServer {
+    pub fn base_url(&self) -> String {
+        format!("http://127.0.0.1:{}", self.port)
+    }
+}
+
 impl Drop for TestServer {
     fn drop(&mut self) {
         let _ = self.child.kill();
@@ -58,12 +70,21 @@ pub fn find_free_port() -> u16 {
 }
 
 pub fn spawn_server(extra_args: &[&str], capture_output: bool) -> TestServer {
+    spawn_server_in(
+        Path::new(env!("CARGO_MANIFEST_DIR")),
+        extra_args,
+        capture_output,
+    )
+}
+
+/// Starts the release binary with `dir` as its working directory, i.e. the served directory.
+pub fn spawn_server_in(dir: &Path, extra_args: &[&str], capture_output: bool) -> TestServer {
     let port = find_free_port();
     let mut cmd = Command::new(release_binary_path());
 
     cmd.arg("--port").arg(port.to_string());
     cmd.args(extra_args);
-    cmd.current_dir(env!("CARGO_MANIFEST_DIR"));
+    cmd.current_dir(dir);
 
     if capture_output {
         cmd.stdout(Stdio::piped()).stderr(Stdio::piped());
@@ -110,3 +131,174 @@ pub fn testdata_path(parts: &[&str]) -> PathBuf {
     }
     path
 }
+
+pub struct RawResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl RawResponse {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+pub fn raw_connection(port: u16) -> BufReader<TcpStream> {
+    let stream = TcpStream::connect(("127.0.0.1", port)).expect("Failed to connect to server");
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .expect("Failed to set read timeout");
+    BufReader::new(stream)
+}
+
+pub fn send_raw(connection: &mut BufReader<TcpStream>, request: &[u8]) {
+    connection
+        .get_mut()
+        .write_all(request)
+        .expect("Failed to write request");
+}
+
+/// Reads a single response framed by Content-Length or chunked encoding, leaving the
+/// connection open.
+pub fn read_raw_response(connection: &mut BufReader<TcpStream>) -> RawResponse {
+    let mut response = read_raw_head(connection);
+    if response
+        .header("transfer-encoding")
+        .is_some_and(|encoding| encoding.eq_ignore_ascii_case("chunked"))
+    {
+        read_chunked_body(connection, &mut response.body);
+        return response;
+    }
+
+    let content_length = response
+        .header("content-length")
+        .and_then(|len| len.parse::<u64>().ok())
+        .unwrap_or(0);
+    connection
+        .by_ref()
+        .take(content_length)
+        .read_to_end(&mut response.body)
+        .expect("Failed to read response body");
+    response
+}
+
+/// Reads only the status line and headers of a response, e.g. one answering a HEAD request.
+pub fn read_raw_head(connection: &mut BufReader<TcpStream>) -> RawResponse {
+    let mut status_line = String::new();
+    connection
+        .read_line(&mut status_line)
+        .expect("Failed to read status line");
+    let status = status_line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|code| code.parse().ok())
+        .unwrap_or_else(|| panic!("Invalid status line: {status_line:?}"));
+
+    let mut headers = Vec::new();
+    loop {
+        let mut line = String::new();
+        connection
+            .read_line(&mut line)
+            .expect("Failed to read header line");
+        if line == "\r\n" || line.is_empty() {
+            break;
+        }
+        if let Some((key, value)) = line.split_once(':') {
+            headers.push((key.trim().to_string(), value.trim().to_string()));
+        }
+    }
+
+    RawResponse {
+        status,
+        headers,
+        body: Vec::new(),
+    }
+}
+
+fn read_chunked_body(connection: &mut BufReader<TcpStream>, body: &mut Vec<u8>) {
+    loop {
+        let mut size_line = String::new();
+        connection
+            .read_line(&mut size_line)
+            .expect("Failed to read chunk size");
+        let size = u64::from_str_radix(size_line.trim(), 16)
+            .unwrap_or_else(|_| panic!("Invalid chunk size line: {size_line:?}"));
+
+        if size > 0 {
+            connection
+                .by_ref()
+                .take(size)
+                .read_to_end(body)
+                .expect("Failed to read chunk");
+        }
+
+        let mut line = String::new();
+        connection
+            .read_line(&mut line)
+            .expect("Failed to read chunk terminator");
+        assert_eq!(line, "\r\n", "Chunk must be terminated by CRLF");
+
+        if size == 0 {
+            return;
+        }
+    }
+}
+
+pub fn is_closed(connection: &mut BufReader<TcpStream>) -> bool {
+    let mut buf = [0; 1];
+    matches!(connection.read(&mut buf), Ok(0))
+}
+
+/// Creates an empty scratch directory under `target/` that tests can upload into.
+pub fn scratch_dir(name: &str) -> (PathBuf, String) {
+    let relative = format!("target/test-scratch/{name}");
+    let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    path.push(&relative);
+    let _ = std::fs::remove_dir_all(&path);
+    std::fs::create_dir_all(&path).expect("Failed to create scratch directory");
+    (path, relative)
+}
+
+/// Runs an in-process server built from the library with the given routes. The server
+/// thread lives until the test binary exits.
+pub fn spawn_library_server<F>(routes: F) -> LibraryServer
+where
+    F: Fn(&mut Router) + Send + Sync + 'static,
+{
+    spawn_configured_library_server(|server| server, routes)
+}
+
+/// Like `spawn_library_server`, but lets the test adjust the server builder first.
+pub fn spawn_configured_library_server<C, F>(configure: C, routes: F) -> LibraryServer
+where
+    C: FnOnce(HttpServer) -> HttpServer + Send + 'static,
+    F: Fn(&mut Router) + Send + Sync + 'static,
+{
+    let port = find_free_port();
+    thread::spawn(move || {
+        configure(HttpServer::build(
+            port,
+            2,
+            None,
+            None,
+            IpAddr::from([127, 0, 0, 1]),
+            false,
+        ))
+        .add_routes(routes)
+        .run()
+        .expect("Library server failed");
+    });
+
+    for _ in 0..50 {
+        if TcpStream::connect(("127.0.0.1", port)).is_ok() {
+            break;
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+
+    LibraryServer { port }
+}
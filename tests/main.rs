@@ -1,5 +1,11 @@
 pub mod common;
 mod suites {
     mod cli_integration;
+    mod connection;
     mod default_api;
+    mod file_ops;
+    mod multipart;
+    mod request_body;
+    mod router;
+    mod upload;
 }
@@ -90,6 +90,62 @@ fn requests_are_logged_when_logging_enabled() {
     }
 }
 
+#[test]
+fn conditional_and_range_responses_are_logged_with_their_status() {
+    let mut server = spawn_server(&["--ip", "127.0.0.1"], true);
+    let url = format!("{}/static/index.html", server.base_url());
+
+    let response = http_client().get(&url).send().expect("Request failed");
+    let etag = response
+        .headers()
+        .get("etag")
+        .and_then(|h| h.to_str().ok())
+        .expect("Missing ETag header")
+        .to_string();
+    let not_modified = http_client()
+        .get(&url)
+        .header("If-None-Match", &etag)
+        .send()
+        .expect("Conditional request failed");
+    assert_eq!(not_modified.status().as_u16(), 304);
+
+    let file_url = format!("{}/api/files?path=Cargo.toml", server.base_url());
+    let partial = http_client()
+        .get(&file_url)
+        .header("Range", "bytes=0-9")
+        .send()
+        .expect("Range request failed");
+    assert_eq!(partial.status().as_u16(), 206);
+    let unsatisfiable = http_client()
+        .get(&file_url)
+        .header("Range", "bytes=100000000-")
+        .send()
+        .expect("Range request failed");
+    assert_eq!(unsatisfiable.status().as_u16(), 416);
+
+    server.child.kill().expect("Failed to kill server");
+    server.child.wait().expect("Failed to wait for server");
+
+    let mut stdout_string = String::new();
+    server
+        .child
+        .stdout
+        .take()
+        .expect("Server stdout was not captured")
+        .read_to_string(&mut stdout_string)
+        .expect("Failed to read server stdout");
+    //log lines are colored, the escape sequences are dropped before matching
+    let log: String = stdout_string
+        .split('\u{1b}')
+        .map(|part| part.split_once('m').map_or(part, |(_, text)| text))
+        .collect();
+    let logged = |entry: &str| log.lines().any(|line| line.ends_with(entry));
+    assert!(logged("304 - GET /static/index.html"), "Log:\n{log}");
+    assert!(logged("206 - GET /api/files"), "Log:\n{log}");
+    assert!(logged("416 - GET /api/files"), "Log:\n{log}");
+    assert!(!logged("200 - GET /api/files"), "Log:\n{log}");
+}
+
 #[test]
 fn cors_argument_adds_cors_headers_to_options_response() {
     let server = spawn_server(&["--ip", "127.0.0.1", "--cors"], false);
@@ -178,6 +234,65 @@ fn compression_argument_sets_gzip_content_encoding() {
     );
 }
 
+#[test]
+fn compressed_files_have_their_own_etag() {
+    let custom_index = testdata_path(&["public", "custom_index.html"]);
+    let custom_index_value = custom_index.to_string_lossy().to_string();
+    let server = spawn_server(
+        &[
+            "--ip",
+            "127.0.0.1",
+            "--compression",
+            "--index",
+            &custom_index_value,
+        ],
+        false,
+    );
+    let url = format!("{}/", server.base_url());
+    let client = Client::builder()
+        .timeout(Duration::from_secs(5))
+        .build()
+        .expect("Failed to build client");
+    let header = |response: &reqwest::blocking::Response, name: &str| {
+        response
+            .headers()
+            .get(name)
+            .and_then(|h| h.to_str().ok())
+            .map(str::to_string)
+    };
+
+    let plain = client.get(&url).send().expect("Request failed");
+    assert_eq!(plain.status().as_u16(), 200);
+    assert_eq!(header(&plain, "content-encoding"), None);
+    assert_eq!(header(&plain, "vary").as_deref(), Some("Accept-Encoding"));
+    let plain_etag = header(&plain, "etag").expect("Missing ETag header");
+
+    let compressed = client
+        .get(&url)
+        .header(ACCEPT_ENCODING, "gzip")
+        .send()
+        .expect("Compressed request failed");
+    assert_eq!(
+        header(&compressed, "content-encoding").as_deref(),
+        Some("gzip")
+    );
+    assert_eq!(
+        header(&compressed, "vary").as_deref(),
+        Some("Accept-Encoding")
+    );
+    let compressed_etag = header(&compressed, "etag").expect("Missing ETag header");
+    assert_ne!(compressed_etag, plain_etag);
+
+    let revalidated = client
+        .get(&url)
+        .header(ACCEPT_ENCODING, "gzip")
+        .header("If-None-Match", &compressed_etag)
+        .send()
+        .expect("Conditional request failed");
+    assert_eq!(revalidated.status().as_u16(), 304);
+    assert_eq!(header(&revalidated, "etag"), Some(compressed_etag));
+}
+
 #[test]
 fn compression_argument_only_compresses_when_accept_encoding_is_set() {
     let server = spawn_server(&["--ip", "127.0.0.1"], false);
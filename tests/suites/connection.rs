@@ -0,0 +1,192 @@
+use std::fs;
+use std::io::Read;
+use std::time::{Duration, Instant};
+
+use flate2::read::GzDecoder;
+
+use crate::common::utils::{
+    is_closed, raw_connection, read_raw_response, send_raw, spawn_server, testdata_path,
+};
+
+#[test]
+fn http11_connection_is_reused_for_multiple_requests() {
+    let server = spawn_server(&["--ip", "127.0.0.1"], false);
+    let mut connection = raw_connection(server.port);
+
+    for _ in 0..3 {
+        send_raw(
+            &mut connection,
+            b"GET /api/directory?path=tests/data HTTP/1.1\r\nHost: localhost\r\n\r\n",
+        );
+        let response = read_raw_response(&mut connection);
+
+        assert_eq!(response.status, 200);
+        assert_eq!(response.header("connection"), Some("keep-alive"));
+        assert!(!response.body.is_empty());
+    }
+}
+
+#[test]
+fn idle_connection_is_closed_when_another_client_waits_for_a_worker() {
+    let server = spawn_server(&["--ip", "127.0.0.1", "--threads", "1"], false);
+    let mut idle = raw_connection(server.port);
+    send_raw(
+        &mut idle,
+        b"GET /api/directory?path=tests/data HTTP/1.1\r\nHost: localhost\r\n\r\n",
+    );
+    assert_eq!(read_raw_response(&mut idle).status, 200);
+
+    let started = Instant::now();
+    let mut waiting = raw_connection(server.port);
+    send_raw(
+        &mut waiting,
+        b"GET /api/directory?path=tests/data HTTP/1.1\r\nHost: localhost\r\n\r\n",
+    );
+    let response = read_raw_response(&mut waiting);
+
+    assert_eq!(response.status, 200);
+    assert!(
+        started.elapsed() < Duration::from_secs(3),
+        "Waited {:?} for the idle connection to time out",
+        started.elapsed()
+    );
+    assert!(
+        is_closed(&mut idle),
+        "Expected idle connection to be closed"
+    );
+}
+
+#[test]
+fn connection_close_header_closes_connection_after_response() {
+    let server = spawn_server(&["--ip", "127.0.0.1"], false);
+    let mut connection = raw_connection(server.port);
+
+    send_raw(
+        &mut connection,
+        b"GET /api/directory?path=tests/data HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+    );
+    let response = read_raw_response(&mut connection);
+
+    assert_eq!(response.status, 200);
+    assert_eq!(response.header("connection"), Some("close"));
+    assert!(
+        is_closed(&mut connection),
+        "Expected server to close connection"
+    );
+}
+
+#[test]
+fn http10_defaults_to_close_unless_keep_alive_requested() {
+    let server = spawn_server(&["--ip", "127.0.0.1"], false);
+
+    let mut connection = raw_connection(server.port);
+    send_raw(
+        &mut connection,
+        b"GET /api/directory?path=tests/data HTTP/1.0\r\n\r\n",
+    );
+    let response = read_raw_response(&mut connection);
+    assert_eq!(response.header("connection"), Some("close"));
+    assert!(
+        is_closed(&mut connection),
+        "Expected server to close connection"
+    );
+
+    let mut connection = raw_connection(server.port);
+    send_raw(
+        &mut connection,
+        b"GET /api/directory?path=tests/data HTTP/1.0\r\nConnection: keep-alive\r\n\r\n",
+    );
+    let response = read_raw_response(&mut connection);
+    assert_eq!(response.header("connection"), Some("keep-alive"));
+
+    send_raw(
+        &mut connection,
+        b"GET /api/directory?path=tests/data HTTP/1.0\r\nConnection: keep-alive\r\n\r\n",
+    );
+    assert_eq!(read_raw_response(&mut connection).status, 200);
+}
+
+#[test]
+fn error_responses_keep_connection_open() {
+    let server = spawn_server(&["--ip", "127.0.0.1"], false);
+    let mut connection = raw_connection(server.port);
+
+    send_raw(
+        &mut connection,
+        b"GET /api/files?path=tests/data/missing.txt HTTP/1.1\r\nHost: localhost\r\n\r\n",
+    );
+    let response = read_raw_response(&mut connection);
+    assert_eq!(response.status, 404);
+
+    send_raw(
+        &mut connection,
+        b"GET /api/directory?path=tests/data HTTP/1.1\r\nHost: localhost\r\n\r\n",
+    );
+    assert_eq!(read_raw_response(&mut connection).status, 200);
+}
+
+#[test]
+fn compressed_file_stream_is_chunked_and_keeps_connection_open() {
+    let custom_index = testdata_path(&["public", "custom_index.html"]);
+    let custom_index_value = custom_index.to_string_lossy().to_string();
+    let expected = fs::read_to_string(&custom_index).expect("Failed to read fixture file");
+    let server = spawn_server(
+        &[
+            "--ip",
+            "127.0.0.1",
+            "--compression",
+            "--index",
+            &custom_index_value,
+        ],
+        false,
+    );
+    let mut connection = raw_connection(server.port);
+
+    for _ in 0..2 {
+        send_raw(
+            &mut connection,
+            b"GET / HTTP/1.1\r\nHost: localhost\r\nAccept-Encoding: gzip\r\n\r\n",
+        );
+        let response = read_raw_response(&mut connection);
+
+        assert_eq!(response.status, 200);
+        assert_eq!(response.header("connection"), Some("keep-alive"));
+        assert_eq!(response.header("transfer-encoding"), Some("chunked"));
+        assert_eq!(response.header("content-encoding"), Some("gzip"));
+        assert_eq!(response.header("content-length"), None);
+
+        let mut body = String::new();
+        GzDecoder::new(&response.body[..])
+            .read_to_string(&mut body)
+            .expect("Failed to decompress body");
+        assert_eq!(body, expected);
+    }
+}
+
+#[test]
+fn compressed_file_stream_is_not_chunked_for_http10() {
+    let custom_index = testdata_path(&["public", "custom_index.html"]);
+    let custom_index_value = custom_index.to_string_lossy().to_string();
+    let expected = fs::read_to_string(&custom_index).expect("Failed to read fixture file");
+    let server = spawn_server(
+        &[
+            "--ip",
+            "127.0.0.1",
+            "--compression",
+            "--index",
+            &custom_index_value,
+        ],
+        false,
+    );
+    let mut connection = raw_connection(server.port);
+
+    send_raw(
+        &mut connection,
+        b"GET / HTTP/1.0\r\nAccept-Encoding: gzip\r\n\r\n",
+    );
+    let response = read_raw_response(&mut connection);
+
+    assert_eq!(response.header("transfer-encoding"), None);
+    assert_eq!(response.header("content-encoding"), None);
+    assert_eq!(String::from_utf8_lossy(&response.body), expected);
+}
@@ -1,4 +1,7 @@
-use crate::common::utils::{http_client, spawn_server, testdata_path};
+use crate::common::utils::{
+    http_client, raw_connection, read_raw_head, read_raw_response, scratch_dir, send_raw,
+    spawn_server, testdata_path,
+};
 use serde_json::Value;
 
 #[test]
@@ -287,3 +290,619 @@ fn api_files_returns_error_for_nonexistent_file() {
         response.status()
     );
 }
+
+/// File outside the served directory, which no request may reach.
+fn outside_file(name: &str) -> std::path::PathBuf {
+    let path = std::env::temp_dir().join(format!("scratch-server-outside-{name}.txt"));
+    std::fs::write(&path, "secret").unwrap();
+    path
+}
+
+#[test]
+fn api_files_rejects_traversal_and_absolute_paths() {
+    let server = spawn_server(&["--ip", "127.0.0.1"], false);
+    let outside = outside_file("traversal");
+    let outside = outside.to_str().unwrap();
+    let traversal = format!("{}{}", "../".repeat(32), outside.trim_start_matches('/'));
+    let encoded_traversal = traversal.replace("../", "%2E%2E%2F");
+
+    for path in [outside, &traversal, &encoded_traversal] {
+        let response = http_client()
+            .get(format!("{}/api/files?path={path}", server.base_url()))
+            .send()
+            .expect("Request failed");
+
+        assert_eq!(response.status().as_u16(), 400, "Expected 400 for {path}");
+        assert_ne!(response.text().unwrap(), "secret");
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn api_files_and_directory_reject_symlink_escapes() {
+    let server = spawn_server(&["--ip", "127.0.0.1"], false);
+    let (dir, relative) = scratch_dir("api_files_symlink_escape");
+    let outside = outside_file("symlink");
+    std::os::unix::fs::symlink(&outside, dir.join("link.txt")).unwrap();
+    std::os::unix::fs::symlink(outside.parent().unwrap(), dir.join("linked_dir")).unwrap();
+
+    for url in [
+        format!("/api/files?path={relative}/link.txt"),
+        format!("/api/directory?path={relative}/linked_dir"),
+    ] {
+        let response = http_client()
+            .get(format!("{}{url}", server.base_url()))
+            .send()
+            .expect("Request failed");
+
+        assert_eq!(response.status().as_u16(), 400, "Expected 400 for {url}");
+    }
+}
+
+#[test]
+fn api_files_decodes_percent_encoded_paths() {
+    let server = spawn_server(&["--ip", "127.0.0.1"], false);
+    let (dir, relative) = scratch_dir("api_files_percent_encoded");
+    std::fs::write(dir.join("with space.txt"), "decoded").unwrap();
+
+    let response = http_client()
+        .get(format!(
+            "{}/api/files?path={relative}/with%20space.txt",
+            server.base_url()
+        ))
+        .send()
+        .expect("Request failed");
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.text().unwrap(), "decoded");
+}
+
+#[test]
+fn api_files_serves_single_byte_range() {
+    let server = spawn_server(&["--ip", "127.0.0.1"], false);
+    let expected = std::fs::read(testdata_path(&["public", "style.css"]))
+        .expect("Failed to read style.css fixture");
+
+    let response = http_client()
+        .get(format!(
+            "{}/api/files?path=tests/data/public/style.css",
+            server.base_url()
+        ))
+        .header("Range", "bytes=10-19")
+        .send()
+        .expect("Range request failed");
+
+    assert_eq!(response.status().as_u16(), 206);
+    assert_eq!(
+        response
+            .headers()
+            .get("content-range")
+            .and_then(|h| h.to_str().ok()),
+        Some(format!("bytes 10-19/{}", expected.len()).as_str())
+    );
+    assert_eq!(
+        response
+            .headers()
+            .get("accept-ranges")
+            .and_then(|h| h.to_str().ok()),
+        Some("bytes")
+    );
+    let body = response.bytes().expect("Failed to read response body");
+    assert_eq!(&body[..], &expected[10..20]);
+}
+
+#[test]
+fn api_files_serves_suffix_and_open_ended_ranges() {
+    let server = spawn_server(&["--ip", "127.0.0.1"], false);
+    let expected = std::fs::read(testdata_path(&["public", "style.css"]))
+        .expect("Failed to read style.css fixture");
+    let url = format!(
+        "{}/api/files?path=tests/data/public/style.css",
+        server.base_url()
+    );
+
+    let suffix = http_client()
+        .get(&url)
+        .header("Range", "bytes=-5")
+        .send()
+        .expect("Suffix range request failed");
+    assert_eq!(suffix.status().as_u16(), 206);
+    assert_eq!(
+        &suffix.bytes().expect("Failed to read body")[..],
+        &expected[expected.len() - 5..]
+    );
+
+    let open_ended = http_client()
+        .get(&url)
+        .header("Range", "bytes=100-")
+        .send()
+        .expect("Open-ended range request failed");
+    assert_eq!(open_ended.status().as_u16(), 206);
+    assert_eq!(
+        &open_ended.bytes().expect("Failed to read body")[..],
+        &expected[100..]
+    );
+}
+
+#[test]
+fn api_files_serves_multiple_ranges_as_multipart_byteranges() {
+    let server = spawn_server(&["--ip", "127.0.0.1"], false);
+    let expected = std::fs::read(testdata_path(&["public", "style.css"]))
+        .expect("Failed to read style.css fixture");
+
+    let response = http_client()
+        .get(format!(
+            "{}/api/files?path=tests/data/public/style.css",
+            server.base_url()
+        ))
+        .header("Range", "bytes=0-4, 20-29")
+        .send()
+        .expect("Multi-range request failed");
+
+    assert_eq!(response.status().as_u16(), 206);
+    let content_type = response
+        .headers()
+        .get("content-type")
+        .and_then(|h| h.to_str().ok())
+        .unwrap_or("")
+        .to_string();
+    assert!(
+        content_type.starts_with("multipart/byteranges; boundary="),
+        "Expected multipart/byteranges, got: {content_type}"
+    );
+    let boundary = content_type.split("boundary=").nth(1).unwrap().to_string();
+
+    let body = response.bytes().expect("Failed to read response body");
+    let body = String::from_utf8_lossy(&body);
+    let parts: Vec<&str> = body.split(&format!("--{boundary}")).collect();
+    assert_eq!(parts.len(), 4, "Expected two parts, got: {body}");
+    assert!(parts[1].contains(&format!("Content-Range: bytes 0-4/{}", expected.len())));
+    assert!(parts[1].ends_with(&format!(
+        "\r\n\r\n{}\r\n",
+        String::from_utf8_lossy(&expected[0..5])
+    )));
+    assert!(parts[2].contains(&format!("Content-Range: bytes 20-29/{}", expected.len())));
+    assert!(parts[2].ends_with(&format!(
+        "\r\n\r\n{}\r\n",
+        String::from_utf8_lossy(&expected[20..30])
+    )));
+    assert_eq!(parts[3], "--\r\n");
+}
+
+#[test]
+fn api_files_merges_overlapping_ranges_and_ignores_empty_range_sets() {
+    let server = spawn_server(&["--ip", "127.0.0.1"], false);
+    let expected = std::fs::read(testdata_path(&["public", "style.css"]))
+        .expect("Failed to read style.css fixture");
+    let url = format!(
+        "{}/api/files?path=tests/data/public/style.css",
+        server.base_url()
+    );
+
+    let repeated = http_client()
+        .get(&url)
+        .header("Range", format!("bytes={}", ["0-"; 32].join(",")))
+        .send()
+        .expect("Range request failed");
+    assert_eq!(repeated.status().as_u16(), 206);
+    assert_eq!(
+        &repeated.bytes().expect("Failed to read body")[..],
+        &expected[..]
+    );
+
+    let overlapping = http_client()
+        .get(&url)
+        .header("Range", "bytes=20-29, 0-4, 3-9, 25-39")
+        .send()
+        .expect("Range request failed");
+    assert_eq!(overlapping.status().as_u16(), 206);
+    let body = String::from_utf8_lossy(&overlapping.bytes().unwrap()).into_owned();
+    assert!(body.contains(&format!("Content-Range: bytes 0-9/{}", expected.len())));
+    assert!(body.contains(&format!("Content-Range: bytes 20-39/{}", expected.len())));
+    assert_eq!(body.matches("Content-Range").count(), 2);
+
+    for range in ["bytes=", "bytes= , ,"] {
+        let response = http_client()
+            .get(&url)
+            .header("Range", range)
+            .send()
+            .expect("Range request failed");
+        assert_eq!(response.status().as_u16(), 200, "Expected 200 for {range}");
+        assert_eq!(&response.bytes().unwrap()[..], &expected[..]);
+    }
+}
+
+#[test]
+fn api_files_returns_416_for_unsatisfiable_range() {
+    let server = spawn_server(&["--ip", "127.0.0.1"], false);
+    let file_size = std::fs::metadata(testdata_path(&["public", "style.css"]))
+        .expect("Failed to read style.css metadata")
+        .len();
+
+    let response = http_client()
+        .get(format!(
+            "{}/api/files?path=tests/data/public/style.css",
+            server.base_url()
+        ))
+        .header("Range", "bytes=100000-")
+        .send()
+        .expect("Range request failed");
+
+    assert_eq!(response.status().as_u16(), 416);
+    assert_eq!(
+        response
+            .headers()
+            .get("content-range")
+            .and_then(|h| h.to_str().ok()),
+        Some(format!("bytes */{file_size}").as_str())
+    );
+}
+
+#[test]
+fn api_files_ignores_range_when_if_range_does_not_match() {
+    let server = spawn_server(&["--ip", "127.0.0.1"], false);
+    let expected = std::fs::read(testdata_path(&["public", "style.css"]))
+        .expect("Failed to read style.css fixture");
+
+    let response = http_client()
+        .get(format!(
+            "{}/api/files?path=tests/data/public/style.css",
+            server.base_url()
+        ))
+        .header("Range", "bytes=0-4")
+        .header("If-Range", "Wed, 21 Oct 2015 07:28:00 GMT")
+        .send()
+        .expect("Range request failed");
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        &response.bytes().expect("Failed to read body")[..],
+        &expected[..]
+    );
+}
+
+#[test]
+fn static_files_return_304_for_matching_if_none_match() {
+    let server = spawn_server(&["--ip", "127.0.0.1"], false);
+    let url = format!("{}/static/index.html", server.base_url());
+
+    let response = http_client().get(&url).send().expect("Request failed");
+    assert_eq!(response.status().as_u16(), 200);
+    let etag = response
+        .headers()
+        .get("etag")
+        .and_then(|h| h.to_str().ok())
+        .expect("Missing ETag header")
+        .to_string();
+
+    let revalidated = http_client()
+        .get(&url)
+        .header("If-None-Match", &etag)
+        .send()
+        .expect("Conditional request failed");
+    assert_eq!(revalidated.status().as_u16(), 304);
+    assert_eq!(
+        revalidated
+            .headers()
+            .get("etag")
+            .and_then(|h| h.to_str().ok()),
+        Some(etag.as_str())
+    );
+
+    let changed = http_client()
+        .get(&url)
+        .header("If-None-Match", "\"outdated\"")
+        .send()
+        .expect("Conditional request failed");
+    assert_eq!(changed.status().as_u16(), 200);
+}
+
+#[test]
+fn api_files_returns_304_when_not_modified_since() {
+    let server = spawn_server(&["--ip", "127.0.0.1"], false);
+    let url = format!(
+        "{}/api/files?path=tests/data/public/style.css",
+        server.base_url()
+    );
+
+    let response = http_client().get(&url).send().expect("Request failed");
+    let last_modified = response
+        .headers()
+        .get("last-modified")
+        .and_then(|h| h.to_str().ok())
+        .expect("Missing Last-Modified header")
+        .to_string();
+
+    let revalidated = http_client()
+        .get(&url)
+        .header("If-Modified-Since", &last_modified)
+        .send()
+        .expect("Conditional request failed");
+    assert_eq!(revalidated.status().as_u16(), 304);
+    assert!(revalidated.bytes().expect("Failed to read body").is_empty());
+
+    let stale = http_client()
+        .get(&url)
+        .header("If-Modified-Since", "Thu, 01 Jan 1970 00:00:00 GMT")
+        .send()
+        .expect("Conditional request failed");
+    assert_eq!(stale.status().as_u16(), 200);
+}
+
+#[test]
+fn api_files_serves_range_when_if_range_matches_etag() {
+    let server = spawn_server(&["--ip", "127.0.0.1"], false);
+    let url = format!(
+        "{}/api/files?path=tests/data/public/style.css",
+        server.base_url()
+    );
+
+    let etag = http_client()
+        .get(&url)
+        .send()
+        .expect("Request failed")
+        .headers()
+        .get("etag")
+        .and_then(|h| h.to_str().ok())
+        .expect("Missing ETag header")
+        .to_string();
+
+    let response = http_client()
+        .get(&url)
+        .header("Range", "bytes=0-4")
+        .header("If-Range", &etag)
+        .send()
+        .expect("Range request failed");
+    assert_eq!(response.status().as_u16(), 206);
+}
+
+#[test]
+fn head_request_on_api_files_returns_headers_without_body() {
+    let server = spawn_server(&["--ip", "127.0.0.1"], false);
+    let file_size = std::fs::metadata(testdata_path(&["public", "style.css"]))
+        .expect("Failed to read style.css metadata")
+        .len();
+    let mut connection = raw_connection(server.port);
+
+    send_raw(
+        &mut connection,
+        b"HEAD /api/files?path=tests/data/public/style.css HTTP/1.1\r\nHost: localhost\r\n\r\n",
+    );
+    let head = read_raw_head(&mut connection);
+
+    assert_eq!(head.status, 200);
+    assert_eq!(
+        head.header("content-length"),
+        Some(file_size.to_string().as_str())
+    );
+    assert!(head
+        .header("content-disposition")
+        .is_some_and(|value| value.contains("style.css")));
+
+    //a body sent after the HEAD response would corrupt the next response on this connection
+    send_raw(
+        &mut connection,
+        b"GET /api/directory?path=tests/data HTTP/1.1\r\nHost: localhost\r\n\r\n",
+    );
+    let next = read_raw_response(&mut connection);
+    assert_eq!(next.status, 200);
+    assert!(next.header("content-type").unwrap_or("").contains("json"));
+}
+
+#[test]
+fn head_request_on_compressed_file_announces_gzip_like_get() {
+    let custom_index = testdata_path(&["public", "custom_index.html"]);
+    let custom_index_value = custom_index.to_string_lossy().to_string();
+    let server = spawn_server(
+        &[
+            "--ip",
+            "127.0.0.1",
+            "--compression",
+            "--index",
+            &custom_index_value,
+        ],
+        false,
+    );
+    let mut connection = raw_connection(server.port);
+
+    send_raw(
+        &mut connection,
+        b"GET / HTTP/1.1\r\nHost: localhost\r\nAccept-Encoding: gzip\r\n\r\n",
+    );
+    let get = read_raw_response(&mut connection);
+    send_raw(
+        &mut connection,
+        b"HEAD / HTTP/1.1\r\nHost: localhost\r\nAccept-Encoding: gzip\r\n\r\n",
+    );
+    let head = read_raw_head(&mut connection);
+
+    assert_eq!(head.status, 200);
+    for name in ["content-encoding", "transfer-encoding", "etag", "vary"] {
+        assert_eq!(head.header(name), get.header(name), "{name} differs");
+    }
+    assert_eq!(head.header("content-encoding"), Some("gzip"));
+    assert_eq!(head.header("content-length"), None);
+
+    //a body sent after the HEAD response would corrupt the next response on this connection
+    send_raw(
+        &mut connection,
+        b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n",
+    );
+    let next = read_raw_response(&mut connection);
+    assert_eq!(next.status, 200);
+    assert_eq!(next.body, std::fs::read(&custom_index).unwrap());
+}
+
+#[test]
+fn head_request_on_json_endpoint_matches_get_content_length() {
+    let server = spawn_server(&["--ip", "127.0.0.1"], false);
+    let url = format!("{}/api/directory?path=tests/data/public", server.base_url());
+
+    let get_length = http_client()
+        .get(&url)
+        .send()
+        .expect("GET request failed")
+        .bytes()
+        .expect("Failed to read body")
+        .len();
+
+    let head = http_client()
+        .head(&url)
+        .send()
+        .expect("HEAD request failed");
+    assert_eq!(head.status().as_u16(), 200);
+    assert_eq!(
+        head.headers()
+            .get("content-length")
+            .and_then(|h| h.to_str().ok()),
+        Some(get_length.to_string().as_str())
+    );
+}
+
+#[test]
+fn api_files_rejects_unsupported_method_with_allow_header() {
+    let server = spawn_server(&["--ip", "127.0.0.1"], false);
+
+    let response = http_client()
+        .post(format!(
+            "{}/api/files?path=tests/data/public/style.css",
+            server.base_url()
+        ))
+        .send()
+        .expect("Request failed");
+
+    assert_eq!(response.status().as_u16(), 405);
+    assert!(response
+        .headers()
+        .get("allow")
+        .and_then(|h| h.to_str().ok())
+        .is_some_and(|allow| allow.contains("GET")));
+}
+
+#[test]
+fn root_option_confines_file_endpoints_to_the_given_directory() {
+    let (dir, _) = scratch_dir("root_option");
+    std::fs::create_dir_all(dir.join("docs")).unwrap();
+    std::fs::write(dir.join("docs/readme.txt"), "inside root").unwrap();
+    let root = dir.to_str().unwrap();
+    let server = spawn_server(&["--ip", "127.0.0.1", "--root", root], false);
+
+    let listing: Value = http_client()
+        .get(format!("{}/api/directory?path=docs", server.base_url()))
+        .send()
+        .expect("Request failed")
+        .json()
+        .expect("Failed to parse JSON");
+    let breadcrumbs: Vec<&str> = listing["paths"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|part| part["full_path"].as_str().unwrap())
+        .collect();
+    assert_eq!(breadcrumbs, vec!["./", "./docs/"]);
+    assert_eq!(listing["files"][0]["path"], "docs/readme.txt");
+
+    let download = http_client()
+        .get(format!(
+            "{}/api/files?path=docs/readme.txt",
+            server.base_url()
+        ))
+        .send()
+        .expect("Request failed");
+    assert_eq!(download.text().unwrap(), "inside root");
+
+    let upload = http_client()
+        .put(format!("{}/api/files?path=docs/new.txt", server.base_url()))
+        .body("uploaded")
+        .send()
+        .expect("Request failed");
+    assert_eq!(upload.status().as_u16(), 201);
+    assert_eq!(
+        std::fs::read_to_string(dir.join("docs/new.txt")).unwrap(),
+        "uploaded"
+    );
+
+    let outside = http_client()
+        .get(format!("{}/api/directory?path=..", server.base_url()))
+        .send()
+        .expect("Request failed");
+    assert_eq!(outside.status().as_u16(), 400);
+}
+
+#[test]
+fn mount_option_shares_directories_as_top_level_folders() {
+    let (music, _) = scratch_dir("mount_music");
+    let (drop, _) = scratch_dir("mount_drop");
+    std::fs::write(music.join("track.txt"), "la la la").unwrap();
+    let music_mount = format!("music={}:ro", music.to_str().unwrap());
+    let drop_mount = format!("drop={}", drop.to_str().unwrap());
+    let server = spawn_server(
+        &[
+            "--ip",
+            "127.0.0.1",
+            "--mount",
+            &music_mount,
+            "--mount",
+            &drop_mount,
+        ],
+        false,
+    );
+
+    let listing: Value = http_client()
+        .get(format!("{}/api/directory?path=./", server.base_url()))
+        .send()
+        .expect("Request failed")
+        .json()
+        .expect("Failed to parse JSON");
+    let mut names: Vec<&str> = listing["files"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|file| file["name"].as_str().unwrap())
+        .collect();
+    names.sort();
+    assert_eq!(names, vec!["drop", "music"]);
+
+    let download = http_client()
+        .get(format!(
+            "{}/api/files?path=music/track.txt",
+            server.base_url()
+        ))
+        .send()
+        .expect("Request failed");
+    assert_eq!(download.text().unwrap(), "la la la");
+
+    let read_only = http_client()
+        .put(format!(
+            "{}/api/files?path=music/new.txt",
+            server.base_url()
+        ))
+        .body("nope")
+        .send()
+        .expect("Request failed");
+    assert_eq!(read_only.status().as_u16(), 403);
+    assert!(!music.join("new.txt").exists());
+
+    let writable = http_client()
+        .put(format!("{}/api/files?path=drop/new.txt", server.base_url()))
+        .body("uploaded")
+        .send()
+        .expect("Request failed");
+    assert_eq!(writable.status().as_u16(), 201);
+    assert_eq!(
+        std::fs::read_to_string(drop.join("new.txt")).unwrap(),
+        "uploaded"
+    );
+
+    let unknown = http_client()
+        .get(format!("{}/api/directory?path=videos", server.base_url()))
+        .send()
+        .expect("Request failed");
+    assert_eq!(unknown.status().as_u16(), 404);
+
+    let outside = http_client()
+        .get(format!("{}/api/directory?path=drop/..", server.base_url()))
+        .send()
+        .expect("Request failed");
+    assert_eq!(outside.status().as_u16(), 400);
+}
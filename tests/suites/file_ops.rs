@@ -0,0 +1,387 @@
+use std::fs;
+
+use serde_json::Value;
+
+use crate::common::utils::{http_client, scratch_dir, spawn_server, TestServer};
+
+fn delete(server: &TestServer, query: &str) -> reqwest::blocking::Response {
+    http_client()
+        .delete(format!("{}/api/files?{query}", server.base_url()))
+        .send()
+        .expect("Request failed")
+}
+
+#[test]
+fn delete_removes_a_file() {
+    let server = spawn_server(&["--ip", "127.0.0.1"], false);
+    let (dir, relative) = scratch_dir("delete_file");
+    fs::write(dir.join("old.txt"), "old").unwrap();
+
+    let response = delete(&server, &format!("path={relative}/old.txt"));
+
+    assert_eq!(response.status().as_u16(), 200);
+    let body: Value = response.json().expect("Failed to parse JSON");
+    assert_eq!(
+        body["removed"],
+        serde_json::json!([format!("{relative}/old.txt")])
+    );
+    assert!(!dir.join("old.txt").exists());
+}
+
+#[test]
+fn delete_requires_recursive_for_directories_with_contents() {
+    let server = spawn_server(&["--ip", "127.0.0.1"], false);
+    let (dir, relative) = scratch_dir("delete_directory");
+    fs::create_dir_all(dir.join("photos/2024")).unwrap();
+    fs::write(dir.join("photos/2024/beach.jpg"), "jpg").unwrap();
+    fs::create_dir(dir.join("empty")).unwrap();
+
+    let refused = delete(&server, &format!("path={relative}/photos"));
+    assert_eq!(refused.status().as_u16(), 409);
+    assert!(dir.join("photos/2024/beach.jpg").exists());
+
+    let empty = delete(&server, &format!("path={relative}/empty"));
+    assert_eq!(empty.status().as_u16(), 200);
+    assert!(!dir.join("empty").exists());
+
+    let response = delete(&server, &format!("path={relative}/photos&recursive=true"));
+    assert_eq!(response.status().as_u16(), 200);
+    let body: Value = response.json().expect("Failed to parse JSON");
+    assert_eq!(
+        body["removed"],
+        serde_json::json!([
+            format!("{relative}/photos/2024/beach.jpg"),
+            format!("{relative}/photos/2024"),
+            format!("{relative}/photos"),
+        ])
+    );
+    assert!(!dir.join("photos").exists());
+}
+
+#[test]
+fn delete_rejects_paths_outside_the_share_and_the_share_itself() {
+    let (dir, _) = scratch_dir("delete_outside");
+    let (outside, _) = scratch_dir("delete_outside_target");
+    fs::create_dir(dir.join("share")).unwrap();
+    fs::write(outside.join("keep.txt"), "keep").unwrap();
+    let root = dir.join("share");
+    let server = spawn_server(
+        &["--ip", "127.0.0.1", "--root", root.to_str().unwrap()],
+        false,
+    );
+
+    let traversal = delete(&server, "path=../../delete_outside_target/keep.txt");
+    assert_eq!(traversal.status().as_u16(), 400);
+    let absolute = delete(
+        &server,
+        &format!("path={}", outside.join("keep.txt").to_str().unwrap()),
+    );
+    assert_eq!(absolute.status().as_u16(), 400);
+    assert!(outside.join("keep.txt").exists());
+
+    let share = delete(&server, "path=./&recursive=true");
+    assert_eq!(share.status().as_u16(), 400);
+    assert!(root.exists());
+
+    let missing = delete(&server, "path=missing.txt");
+    assert_eq!(missing.status().as_u16(), 404);
+}
+
+#[cfg(unix)]
+#[test]
+fn delete_removes_symlinks_without_following_them() {
+    let server = spawn_server(&["--ip", "127.0.0.1"], false);
+    let (dir, relative) = scratch_dir("delete_symlink");
+    fs::create_dir(dir.join("target")).unwrap();
+    fs::write(dir.join("target/keep.txt"), "keep").unwrap();
+    std::os::unix::fs::symlink(dir.join("target"), dir.join("link")).unwrap();
+
+    let response = delete(&server, &format!("path={relative}/link&recursive=true"));
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(fs::symlink_metadata(dir.join("link")).is_err());
+    assert!(dir.join("target/keep.txt").exists());
+}
+
+#[test]
+fn delete_requires_credentials_and_respects_read_only_mounts() {
+    let (dir, relative) = scratch_dir("delete_auth");
+    fs::write(dir.join("secret.txt"), "secret").unwrap();
+    let server = spawn_server(&["--ip", "127.0.0.1", "--auth", "user:pass"], false);
+
+    let unauthorized = delete(&server, &format!("path={relative}/secret.txt"));
+    assert_eq!(unauthorized.status().as_u16(), 401);
+    assert!(dir.join("secret.txt").exists());
+
+    let mount = format!("docs={}:ro", dir.to_str().unwrap());
+    let server = spawn_server(&["--ip", "127.0.0.1", "--mount", &mount], false);
+    let read_only = delete(&server, "path=docs/secret.txt");
+    assert_eq!(read_only.status().as_u16(), 403);
+    assert!(dir.join("secret.txt").exists());
+}
+
+fn move_path(server: &TestServer, query: &str) -> reqwest::blocking::Response {
+    http_client()
+        .post(format!("{}/api/files/move?{query}", server.base_url()))
+        .send()
+        .expect("Request failed")
+}
+
+#[test]
+fn move_renames_files_and_directories() {
+    let server = spawn_server(&["--ip", "127.0.0.1"], false);
+    let (dir, relative) = scratch_dir("move_rename");
+    fs::create_dir_all(dir.join("drafts/images")).unwrap();
+    fs::write(dir.join("drafts/notes.txt"), "notes").unwrap();
+    fs::write(dir.join("drafts/images/cover.png"), "png").unwrap();
+
+    let renamed = move_path(
+        &server,
+        &format!("from={relative}/drafts/notes.txt&to={relative}/drafts/final.txt"),
+    );
+    assert_eq!(renamed.status().as_u16(), 201);
+    let body: Value = renamed.json().expect("Failed to parse JSON");
+    assert_eq!(body["path"], format!("{relative}/drafts/final.txt"));
+    assert_eq!(body["file_type"], "File");
+    assert!(!dir.join("drafts/notes.txt").exists());
+    assert_eq!(
+        fs::read_to_string(dir.join("drafts/final.txt")).unwrap(),
+        "notes"
+    );
+
+    let moved = move_path(
+        &server,
+        &format!("from={relative}/drafts&to={relative}/published/2024"),
+    );
+    assert_eq!(moved.status().as_u16(), 201);
+    let body: Value = moved.json().expect("Failed to parse JSON");
+    assert_eq!(body["file_type"], "Directory");
+    assert!(!dir.join("drafts").exists());
+    assert!(dir.join("published/2024/images/cover.png").exists());
+}
+
+#[test]
+fn move_follows_the_upload_conflict_rules() {
+    let server = spawn_server(&["--ip", "127.0.0.1", "--on-conflict", "reject"], false);
+    let (dir, relative) = scratch_dir("move_conflict");
+    fs::write(dir.join("a.txt"), "a").unwrap();
+    fs::write(dir.join("b.txt"), "b").unwrap();
+    fs::create_dir(dir.join("folder")).unwrap();
+    let query = format!("from={relative}/a.txt&to={relative}/b.txt");
+
+    let rejected = move_path(&server, &query);
+    assert_eq!(rejected.status().as_u16(), 409);
+    assert_eq!(fs::read_to_string(dir.join("b.txt")).unwrap(), "b");
+
+    let renamed = move_path(&server, &format!("{query}&conflict=rename"));
+    assert_eq!(renamed.status().as_u16(), 201);
+    let body: Value = renamed.json().expect("Failed to parse JSON");
+    assert_eq!(body["name"], "b (1).txt");
+    assert_eq!(fs::read_to_string(dir.join("b (1).txt")).unwrap(), "a");
+
+    let query = format!("from={relative}/b%20(1).txt&to={relative}/b.txt");
+    let overwritten = move_path(&server, &format!("{query}&conflict=overwrite"));
+    assert_eq!(overwritten.status().as_u16(), 200);
+    assert_eq!(fs::read_to_string(dir.join("b.txt")).unwrap(), "a");
+    assert!(!dir.join("b (1).txt").exists());
+
+    let onto_directory = move_path(
+        &server,
+        &format!("from={relative}/b.txt&to={relative}/folder&conflict=overwrite"),
+    );
+    assert_eq!(onto_directory.status().as_u16(), 400);
+    assert!(dir.join("folder").is_dir());
+}
+
+#[test]
+fn move_rejects_invalid_sources_and_destinations() {
+    let server = spawn_server(&["--ip", "127.0.0.1", "--deny-ext", "exe"], false);
+    let (dir, relative) = scratch_dir("move_invalid");
+    fs::create_dir(dir.join("folder")).unwrap();
+    fs::write(dir.join("tool.txt"), "text").unwrap();
+
+    let into_itself = move_path(
+        &server,
+        &format!("from={relative}/folder&to={relative}/folder/inner"),
+    );
+    assert_eq!(into_itself.status().as_u16(), 400);
+
+    let outside = move_path(
+        &server,
+        &format!("from={relative}/tool.txt&to=../escaped.txt"),
+    );
+    assert_eq!(outside.status().as_u16(), 400);
+    assert!(dir.join("tool.txt").exists());
+
+    let denied = move_path(
+        &server,
+        &format!("from={relative}/tool.txt&to={relative}/tool.exe"),
+    );
+    assert_eq!(denied.status().as_u16(), 415);
+    assert!(!dir.join("tool.exe").exists());
+
+    let missing = move_path(
+        &server,
+        &format!("from={relative}/missing.txt&to={relative}/found.txt"),
+    );
+    assert_eq!(missing.status().as_u16(), 404);
+}
+
+#[test]
+fn move_between_mounts_respects_read_only_mounts() {
+    let (archive, _) = scratch_dir("move_mount_archive");
+    let (inbox, _) = scratch_dir("move_mount_inbox");
+    fs::write(archive.join("old.txt"), "old").unwrap();
+    fs::write(inbox.join("new.txt"), "new").unwrap();
+    let archive_mount = format!("archive={}:ro", archive.to_str().unwrap());
+    let inbox_mount = format!("inbox={}", inbox.to_str().unwrap());
+    let server = spawn_server(
+        &[
+            "--ip",
+            "127.0.0.1",
+            "--mount",
+            &archive_mount,
+            "--mount",
+            &inbox_mount,
+        ],
+        false,
+    );
+
+    let out_of_read_only = move_path(&server, "from=archive/old.txt&to=inbox/old.txt");
+    assert_eq!(out_of_read_only.status().as_u16(), 403);
+    let into_read_only = move_path(&server, "from=inbox/new.txt&to=archive/new.txt");
+    assert_eq!(into_read_only.status().as_u16(), 403);
+    assert!(archive.join("old.txt").exists());
+    assert!(inbox.join("new.txt").exists());
+
+    let within = move_path(&server, "from=inbox/new.txt&to=inbox/sorted/new.txt");
+    assert_eq!(within.status().as_u16(), 201);
+    let body: Value = within.json().expect("Failed to parse JSON");
+    assert_eq!(body["path"], "inbox/sorted/new.txt");
+}
+
+fn create_directory(server: &TestServer, query: &str) -> reqwest::blocking::Response {
+    http_client()
+        .post(format!("{}/api/directory?{query}", server.base_url()))
+        .send()
+        .expect("Request failed")
+}
+
+#[test]
+fn create_directory_supports_parents() {
+    let server = spawn_server(&["--ip", "127.0.0.1"], false);
+    let (dir, relative) = scratch_dir("create_directory");
+
+    let missing_parent = create_directory(&server, &format!("path={relative}/a/b"));
+    assert_eq!(missing_parent.status().as_u16(), 404);
+    assert!(!dir.join("a").exists());
+
+    let created = create_directory(&server, &format!("path={relative}/a/b&parents=true"));
+    assert_eq!(created.status().as_u16(), 201);
+    let body: Value = created.json().expect("Failed to parse JSON");
+    assert_eq!(body["path"], format!("{relative}/a/b"));
+    assert_eq!(body["file_type"], "Directory");
+    assert!(dir.join("a/b").is_dir());
+
+    let existing = create_directory(&server, &format!("path={relative}/a/b&parents=true"));
+    assert_eq!(existing.status().as_u16(), 200);
+    let conflict = create_directory(&server, &format!("path={relative}/a/b"));
+    assert_eq!(conflict.status().as_u16(), 409);
+
+    let single = create_directory(&server, &format!("path={relative}/a/c"));
+    assert_eq!(single.status().as_u16(), 201);
+    assert!(dir.join("a/c").is_dir());
+
+    let outside = create_directory(&server, "path=../escaped&parents=true");
+    assert_eq!(outside.status().as_u16(), 400);
+}
+
+fn copy_path(server: &TestServer, query: &str) -> reqwest::blocking::Response {
+    http_client()
+        .post(format!("{}/api/files/copy?{query}", server.base_url()))
+        .send()
+        .expect("Request failed")
+}
+
+#[test]
+fn copy_duplicates_files_and_directories() {
+    let server = spawn_server(&["--ip", "127.0.0.1"], false);
+    let (dir, relative) = scratch_dir("copy_entries");
+    fs::create_dir_all(dir.join("album/disc 1")).unwrap();
+    fs::write(dir.join("album/cover.jpg"), "jpg").unwrap();
+    fs::write(dir.join("album/disc 1/track.mp3"), "mp3").unwrap();
+
+    let file = copy_path(
+        &server,
+        &format!("from={relative}/album/cover.jpg&to={relative}/cover.jpg"),
+    );
+    assert_eq!(file.status().as_u16(), 201);
+    assert_eq!(fs::read_to_string(dir.join("cover.jpg")).unwrap(), "jpg");
+    assert!(dir.join("album/cover.jpg").exists());
+
+    let directory = copy_path(
+        &server,
+        &format!("from={relative}/album&to={relative}/backup/album"),
+    );
+    assert_eq!(directory.status().as_u16(), 201);
+    let body: Value = directory.json().expect("Failed to parse JSON");
+    assert_eq!(body["path"], format!("{relative}/backup/album"));
+    assert_eq!(body["file_type"], "Directory");
+    assert_eq!(
+        fs::read_to_string(dir.join("backup/album/disc 1/track.mp3")).unwrap(),
+        "mp3"
+    );
+    assert!(dir.join("album/disc 1/track.mp3").exists());
+
+    let renamed = copy_path(
+        &server,
+        &format!("from={relative}/album&to={relative}/backup/album&conflict=rename"),
+    );
+    assert_eq!(renamed.status().as_u16(), 201);
+    assert!(dir.join("backup/album (1)/cover.jpg").exists());
+
+    let into_itself = copy_path(
+        &server,
+        &format!("from={relative}/album&to={relative}/album/copy"),
+    );
+    assert_eq!(into_itself.status().as_u16(), 400);
+}
+
+#[test]
+fn copy_reads_from_read_only_mounts_but_respects_quotas() {
+    let (archive, _) = scratch_dir("copy_mount_archive");
+    let (inbox, _) = scratch_dir("copy_mount_inbox");
+    fs::write(archive.join("small.txt"), "small").unwrap();
+    fs::write(archive.join("large.txt"), "x".repeat(2000)).unwrap();
+    let archive_mount = format!("archive={}:ro", archive.to_str().unwrap());
+    let inbox_mount = format!("inbox={}", inbox.to_str().unwrap());
+    let server = spawn_server(
+        &[
+            "--ip",
+            "127.0.0.1",
+            "--mount",
+            &archive_mount,
+            "--mount",
+            &inbox_mount,
+            "--quota",
+            "inbox=1K",
+        ],
+        false,
+    );
+
+    let copied = copy_path(&server, "from=archive/small.txt&to=inbox/small.txt");
+    assert_eq!(copied.status().as_u16(), 201);
+    assert_eq!(
+        fs::read_to_string(inbox.join("small.txt")).unwrap(),
+        "small"
+    );
+
+    let too_large = copy_path(&server, "from=archive/large.txt&to=inbox/large.txt");
+    assert_eq!(too_large.status().as_u16(), 507);
+    assert!(!inbox.join("large.txt").exists());
+
+    let read_only = copy_path(&server, "from=inbox/small.txt&to=archive/small-copy.txt");
+    assert_eq!(read_only.status().as_u16(), 403);
+    let mkdir = create_directory(&server, "path=archive/new");
+    assert_eq!(mkdir.status().as_u16(), 403);
+}
@@ -0,0 +1,110 @@
+use std::io::{self, Cursor, Read};
+
+use scratch_server::{Multipart, Part};
+
+/// Hands out the wrapped bytes a few at a time so boundaries straddle reads.
+struct Trickle<R> {
+    inner: R,
+    step: usize,
+}
+
+impl<R: Read> Read for Trickle<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = buf.len().min(self.step);
+        self.inner.read(&mut buf[..len])
+    }
+}
+
+fn read_part<R: Read>(part: &mut Part<'_, R>) -> String {
+    let mut content = String::new();
+    part.read_to_string(&mut content)
+        .expect("Failed to read part");
+    content
+}
+
+const FORM: &str = "preamble to ignore\r\n\
+    --XBOUNDARY\r\n\
+    Content-Disposition: form-data; name=\"comment\"\r\n\r\n\
+    first line\r\nsecond --XBOUNDAR line\r\n\
+    --XBOUNDARY  \r\n\
+    Content-Disposition: form-data; name=\"file\"; filename=\"a;b \\\"c\\\".txt\"\r\n\
+    Content-Type: text/plain\r\n\r\n\
+    file body\r\n\
+    --XBOUNDARY--\r\n\
+    epilogue to ignore";
+
+#[test]
+fn multipart_yields_fields_and_files_in_order() {
+    for step in [1, 3, 7, 4096] {
+        let reader = Trickle {
+            inner: Cursor::new(FORM),
+            step,
+        };
+        let mut multipart = Multipart::new(reader, "XBOUNDARY");
+
+        let mut comment = multipart.next_part().unwrap().expect("Missing field part");
+        assert_eq!(comment.name(), Some("comment"));
+        assert_eq!(comment.file_name(), None);
+        assert_eq!(
+            read_part(&mut comment),
+            "first line\r\nsecond --XBOUNDAR line"
+        );
+
+        let mut file = multipart.next_part().unwrap().expect("Missing file part");
+        assert_eq!(file.name(), Some("file"));
+        assert_eq!(file.file_name(), Some("a;b \"c\".txt"));
+        assert_eq!(file.content_type(), Some("text/plain"));
+        assert_eq!(read_part(&mut file), "file body");
+
+        assert!(multipart.next_part().unwrap().is_none());
+        assert!(multipart.next_part().unwrap().is_none());
+    }
+}
+
+#[test]
+fn multipart_skips_unread_parts() {
+    let mut multipart = Multipart::new(Cursor::new(FORM), "XBOUNDARY");
+    multipart.next_part().unwrap().expect("Missing field part");
+
+    let mut file = multipart.next_part().unwrap().expect("Missing file part");
+    assert_eq!(read_part(&mut file), "file body");
+}
+
+#[test]
+fn multipart_prefers_extended_filename() {
+    let body = "--b\r\n\
+        Content-Disposition: form-data; name=file; filename=\"fallback.txt\"; \
+        filename*=UTF-8''%E2%82%AC%20rates.txt\r\n\r\n\
+        content\r\n--b--";
+    let mut multipart =
+        Multipart::from_content_type("multipart/form-data; boundary=\"b\"", Cursor::new(body))
+            .expect("Boundary not parsed");
+
+    let part = multipart.next_part().unwrap().expect("Missing file part");
+    assert_eq!(part.name(), Some("file"));
+    assert_eq!(part.file_name(), Some("€ rates.txt"));
+}
+
+#[test]
+fn multipart_rejects_truncated_body() {
+    let body = "--b\r\nContent-Disposition: form-data; name=\"file\"\r\n\r\npartial";
+    let mut multipart = Multipart::new(Cursor::new(body), "b");
+
+    let mut part = multipart.next_part().unwrap().expect("Missing part");
+    assert!(part.read_to_end(&mut Vec::new()).is_err());
+
+    let err = match multipart.next_part() {
+        Ok(_) => panic!("Truncated body was accepted"),
+        Err(err) => err,
+    };
+    assert_eq!(err.into_response().status_code, 400);
+}
+
+#[test]
+fn multipart_requires_boundary() {
+    let err = match Multipart::from_content_type("multipart/form-data", Cursor::new("")) {
+        Ok(_) => panic!("Missing boundary was accepted"),
+        Err(err) => err,
+    };
+    assert_eq!(err.into_response().status_code, 400);
+}
@@ -0,0 +1,316 @@
+use std::{fs, io, thread, time::Duration};
+
+use scratch_server::{Body, HttpMethod, HttpResponse, Router};
+use serde_json::{json, Value};
+
+use crate::common::utils::{
+    http_client, raw_connection, read_raw_response, scratch_dir, send_raw,
+    spawn_configured_library_server, spawn_library_server, spawn_server,
+};
+
+fn chunked(body: &[u8], chunk_size: usize) -> Vec<u8> {
+    let mut encoded = Vec::new();
+    for chunk in body.chunks(chunk_size) {
+        encoded.extend_from_slice(format!("{:x};ext=1\r\n", chunk.len()).as_bytes());
+        encoded.extend_from_slice(chunk);
+        encoded.extend_from_slice(b"\r\n");
+    }
+    encoded.extend_from_slice(b"0\r\nX-Trailer: done\r\n\r\n");
+    encoded
+}
+
+#[test]
+fn chunked_multipart_upload_writes_file() {
+    let server = spawn_server(&["--ip", "127.0.0.1"], false);
+    let (dir, relative) = scratch_dir("chunked_multipart_upload");
+
+    let content = "chunked upload content\r\nwith several lines\r\n".repeat(50);
+    let multipart = format!(
+        "--XBOUNDARY\r\n\
+        Content-Disposition: form-data; name=\"file\"; filename=\"chunked.txt\"\r\n\
+        Content-Type: text/plain\r\n\r\n\
+        {content}\r\n--XBOUNDARY--\r\n"
+    );
+
+    let mut request = format!(
+        "POST /api/upload?path={relative} HTTP/1.1\r\n\
+        Host: localhost\r\n\
+        Content-Type: multipart/form-data; boundary=XBOUNDARY\r\n\
+        Transfer-Encoding: chunked\r\n\r\n"
+    )
+    .into_bytes();
+    request.extend(chunked(multipart.as_bytes(), 100));
+
+    let mut connection = raw_connection(server.port);
+    send_raw(&mut connection, &request);
+    let response = read_raw_response(&mut connection);
+
+    assert_eq!(response.status, 200);
+    let uploaded = fs::read_to_string(dir.join("chunked.txt")).expect("Uploaded file missing");
+    assert_eq!(uploaded, content);
+}
+
+#[test]
+fn chunked_body_is_fully_consumed_before_next_request() {
+    let server = spawn_server(&["--ip", "127.0.0.1"], false);
+    let mut connection = raw_connection(server.port);
+
+    let mut request =
+        b"GET /api/directory?path=tests/data HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: chunked\r\n\r\n"
+            .to_vec();
+    request.extend(chunked(b"{\"ignored\": \"payload\"}", 5));
+    send_raw(&mut connection, &request);
+    assert_eq!(read_raw_response(&mut connection).status, 200);
+
+    send_raw(
+        &mut connection,
+        b"GET /api/directory?path=tests/data HTTP/1.1\r\nHost: localhost\r\n\r\n",
+    );
+    assert_eq!(read_raw_response(&mut connection).status, 200);
+}
+
+#[test]
+fn request_with_content_length_and_transfer_encoding_is_rejected() {
+    let server = spawn_server(&["--ip", "127.0.0.1"], false);
+    let mut connection = raw_connection(server.port);
+
+    send_raw(
+        &mut connection,
+        b"POST /api/directory HTTP/1.1\r\nHost: localhost\r\nContent-Length: 5\r\nTransfer-Encoding: chunked\r\n\r\n0\r\n\r\n",
+    );
+    let response = read_raw_response(&mut connection);
+
+    assert_eq!(response.status, 400);
+    assert_eq!(response.header("connection"), Some("close"));
+}
+
+#[test]
+fn duplicate_framing_headers_are_rejected() {
+    let server = spawn_server(&["--ip", "127.0.0.1"], false);
+    let requests: [&[u8]; 4] = [
+        b"POST /api/directory HTTP/1.1\r\nHost: localhost\r\nContent-Length: 3\r\ncontent-length: 40\r\n\r\nabcGET / HTTP/1.1\r\nHost: localhost\r\n\r\n",
+        b"POST /api/directory HTTP/1.1\r\nHost: localhost\r\nContent-Length: 3\r\nContent-Length: 40\r\n\r\nabc",
+        b"POST /api/directory HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: chunked\r\ntransfer-encoding: chunked\r\n\r\n0\r\n\r\n",
+        b"POST /api/directory HTTP/1.1\r\nHost: localhost\r\ncontent-length: 5\r\nTRANSFER-ENCODING: chunked\r\n\r\n0\r\n\r\n",
+    ];
+
+    for request in requests {
+        let mut connection = raw_connection(server.port);
+        send_raw(&mut connection, request);
+        let response = read_raw_response(&mut connection);
+
+        assert_eq!(response.status, 400);
+        assert_eq!(response.header("connection"), Some("close"));
+    }
+}
+
+#[test]
+fn repeated_identical_content_length_is_accepted() {
+    let server = spawn_library_server(byte_count_route);
+    let mut connection = raw_connection(server.port);
+
+    send_raw(
+        &mut connection,
+        b"POST /count HTTP/1.1\r\nHost: localhost\r\nContent-Length: 3\r\ncontent-length: 3\r\n\r\nabc",
+    );
+    let response = read_raw_response(&mut connection);
+
+    assert_eq!(response.status, 200);
+    let body: Value = serde_json::from_slice(&response.body).expect("Failed to parse JSON");
+    assert_eq!(body["count"], 3);
+}
+
+#[test]
+fn signed_content_length_and_chunk_size_are_rejected() {
+    let server = spawn_library_server(byte_count_route);
+    let requests: [&[u8]; 2] = [
+        b"POST /count HTTP/1.1\r\nHost: localhost\r\nContent-Length: +3\r\n\r\nabc",
+        b"POST /count HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: chunked\r\n\r\n+3\r\nabc\r\n0\r\n\r\n",
+    ];
+
+    for request in requests {
+        let mut connection = raw_connection(server.port);
+        send_raw(&mut connection, request);
+        let response = read_raw_response(&mut connection);
+
+        assert_ne!(response.status, 200);
+        assert_eq!(response.header("connection"), Some("close"));
+    }
+}
+
+#[test]
+fn overlong_chunk_lines_are_rejected() {
+    let server = spawn_library_server(byte_count_route);
+    let size_line = [b"0".repeat(64 * 1024), b"\r\n\r\n".to_vec()].concat();
+    let trailers = [
+        b"0\r\n".to_vec(),
+        b"X-Trailer: done\r\n".repeat(100),
+        b"\r\n".to_vec(),
+    ]
+    .concat();
+
+    for body in [size_line, trailers] {
+        let mut connection = raw_connection(server.port);
+        let mut request =
+            b"POST /count HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: chunked\r\n\r\n"
+                .to_vec();
+        request.extend(body);
+        send_raw(&mut connection, &request);
+        let response = read_raw_response(&mut connection);
+
+        assert_ne!(response.status, 200);
+        assert_eq!(response.header("connection"), Some("close"));
+    }
+}
+
+#[test]
+fn unsupported_transfer_encoding_is_rejected() {
+    let server = spawn_server(&["--ip", "127.0.0.1"], false);
+    let mut connection = raw_connection(server.port);
+
+    send_raw(
+        &mut connection,
+        b"POST /api/directory HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: gzip\r\n\r\n",
+    );
+
+    assert_eq!(read_raw_response(&mut connection).status, 501);
+}
+
+fn byte_count_route(router: &mut Router) {
+    router.add_handler(
+        "/count",
+        HttpMethod::POST,
+        |mut request| {
+            let body = request.body().ok_or("Missing body")?;
+            let count = io::copy(body, &mut io::sink())?;
+            let trailer = body
+                .trailers()
+                .iter()
+                .find(|(key, _)| key == "X-Trailer")
+                .map(|(_, value)| value.clone());
+            Ok(HttpResponse::new(
+                Some(Body::Json(json!({"count": count, "trailer": trailer}))),
+                None,
+                200,
+            ))
+        },
+        false,
+    );
+}
+
+#[test]
+fn handlers_can_stream_chunked_body_and_read_trailers() {
+    let server = spawn_library_server(byte_count_route);
+    let mut connection = raw_connection(server.port);
+
+    let mut request =
+        b"POST /count HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: chunked\r\n\r\n".to_vec();
+    request.extend(chunked(&vec![7u8; 100_000], 4096));
+    send_raw(&mut connection, &request);
+    let response = read_raw_response(&mut connection);
+
+    assert_eq!(response.status, 200);
+    let body: Value = serde_json::from_slice(&response.body).expect("Failed to parse JSON");
+    assert_eq!(body["count"], 100_000);
+    assert_eq!(body["trailer"], "done");
+}
+
+#[test]
+fn binary_body_reaches_handler_unchanged() {
+    let server = spawn_library_server(|router| {
+        router.add_handler(
+            "/echo",
+            HttpMethod::POST,
+            |mut request| {
+                Ok(HttpResponse::new(
+                    Some(Body::Json(json!(request.read_body()?))),
+                    None,
+                    200,
+                ))
+            },
+            false,
+        );
+    });
+    let payload: Vec<u8> = (0..=255).collect();
+
+    let body: Value = http_client()
+        .post(format!("{}/echo", server.base_url()))
+        .body(payload.clone())
+        .send()
+        .expect("Request failed")
+        .json()
+        .expect("Failed to parse JSON");
+
+    assert_eq!(body, json!(payload));
+}
+
+#[test]
+fn body_over_max_size_is_rejected_with_413() {
+    let server =
+        spawn_configured_library_server(|server| server.with_max_body_size(1024), byte_count_route);
+
+    let announced = http_client()
+        .post(format!("{}/count", server.base_url()))
+        .body(vec![1u8; 2048])
+        .send()
+        .expect("Request failed");
+    assert_eq!(announced.status().as_u16(), 413);
+
+    let mut connection = raw_connection(server.port);
+    let mut request =
+        b"POST /count HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: chunked\r\n\r\n".to_vec();
+    request.extend(chunked(&[1u8; 2048], 512));
+    send_raw(&mut connection, &request);
+    let streamed = read_raw_response(&mut connection);
+    assert_eq!(streamed.status, 413);
+    assert_eq!(streamed.header("connection"), Some("close"));
+
+    let within_limit = http_client()
+        .post(format!("{}/count", server.base_url()))
+        .body(vec![1u8; 1024])
+        .send()
+        .expect("Request failed");
+    assert_eq!(within_limit.status().as_u16(), 200);
+}
+
+#[test]
+fn stalled_body_is_not_cut_off_by_the_keep_alive_timeout() {
+    let server = spawn_configured_library_server(
+        |server| server.with_keep_alive(Duration::from_secs(1), 100),
+        byte_count_route,
+    );
+    let mut connection = raw_connection(server.port);
+
+    send_raw(
+        &mut connection,
+        b"POST /count HTTP/1.1\r\nHost: localhost\r\nContent-Length: 10\r\n\r\nhello",
+    );
+    thread::sleep(Duration::from_millis(2500));
+    send_raw(&mut connection, b"world");
+    let response = read_raw_response(&mut connection);
+
+    assert_eq!(response.status, 200);
+    let body: Value = serde_json::from_slice(&response.body).expect("Failed to parse JSON");
+    assert_eq!(body["count"], 10);
+}
+
+#[test]
+fn unread_body_is_discarded_before_next_request() {
+    let server = spawn_library_server(|router| {
+        router.add_handler(
+            "/ignore",
+            HttpMethod::POST,
+            |_| Ok(HttpResponse::new(None, None, 204)),
+            false,
+        );
+    });
+    let mut connection = raw_connection(server.port);
+
+    for _ in 0..2 {
+        send_raw(
+            &mut connection,
+            b"POST /ignore HTTP/1.1\r\nHost: localhost\r\nContent-Length: 11\r\n\r\nhello world",
+        );
+        assert_eq!(read_raw_response(&mut connection).status, 204);
+    }
+}
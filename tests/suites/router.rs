@@ -0,0 +1,236 @@
+use scratch_server::{Body, HttpMethod, HttpResponse};
+use serde_json::{json, Value};
+
+use crate::common::utils::{http_client, spawn_configured_library_server, spawn_library_server};
+
+fn text_response(text: &str) -> HttpResponse {
+    HttpResponse::new(
+        Some(Body::Text(text.to_string())),
+        Some("text/plain".to_string()),
+        200,
+    )
+}
+
+#[test]
+fn routes_with_same_path_are_selected_by_method() {
+    let server = spawn_library_server(|router| {
+        router.add_route(
+            "/items",
+            HttpMethod::GET,
+            |_, _| Ok(text_response("get")),
+            false,
+        );
+        router.add_route(
+            "/items",
+            HttpMethod::DELETE,
+            |_, _| Ok(text_response("delete")),
+            false,
+        );
+    });
+    let url = format!("{}/items", server.base_url());
+
+    let get = http_client().get(&url).send().expect("GET request failed");
+    assert_eq!(get.text().expect("Failed to read body"), "get");
+
+    let delete = http_client()
+        .delete(&url)
+        .send()
+        .expect("DELETE request failed");
+    assert_eq!(delete.status().as_u16(), 200);
+    assert_eq!(delete.text().expect("Failed to read body"), "delete");
+}
+
+#[test]
+fn unmatched_method_returns_405_with_allow_header() {
+    let server = spawn_library_server(|router| {
+        router.add_route(
+            "/items",
+            HttpMethod::GET,
+            |_, _| Ok(text_response("get")),
+            false,
+        );
+        router.add_route(
+            "/items",
+            HttpMethod::DELETE,
+            |_, _| Ok(text_response("delete")),
+            false,
+        );
+    });
+
+    let response = http_client()
+        .put(format!("{}/items", server.base_url()))
+        .send()
+        .expect("PUT request failed");
+
+    assert_eq!(response.status().as_u16(), 405);
+    assert_eq!(
+        response
+            .headers()
+            .get("allow")
+            .and_then(|h| h.to_str().ok()),
+        Some("GET, HEAD, DELETE, OPTIONS")
+    );
+}
+
+#[test]
+fn options_request_lists_allowed_methods() {
+    let server = spawn_library_server(|router| {
+        router.add_route(
+            "/items",
+            HttpMethod::POST,
+            |_, _| Ok(text_response("post")),
+            false,
+        );
+        router.add_route(
+            "/items/{id}",
+            HttpMethod::GET,
+            |_, _| Ok(text_response("get")),
+            false,
+        );
+    });
+
+    let response = http_client()
+        .request(
+            reqwest::Method::OPTIONS,
+            format!("{}/items", server.base_url()),
+        )
+        .send()
+        .expect("OPTIONS request failed");
+
+    assert_eq!(response.status().as_u16(), 204);
+    assert_eq!(
+        response
+            .headers()
+            .get("allow")
+            .and_then(|h| h.to_str().ok()),
+        Some("POST, OPTIONS")
+    );
+}
+
+#[test]
+fn unknown_path_returns_404_for_any_method() {
+    let server = spawn_library_server(|router| {
+        router.add_route(
+            "/items",
+            HttpMethod::GET,
+            |_, _| Ok(text_response("get")),
+            false,
+        );
+    });
+
+    let response = http_client()
+        .post(format!("{}/other", server.base_url()))
+        .send()
+        .expect("POST request failed");
+
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[test]
+fn request_handlers_can_inspect_request_details() {
+    let server = spawn_library_server(|router| {
+        router.add_handler(
+            "/inspect/{id}",
+            HttpMethod::POST,
+            |mut request| {
+                let body = request.read_body()?;
+                Ok(HttpResponse::new(
+                    Some(Body::Json(json!({
+                        "method": request.method(),
+                        "path": request.path(),
+                        "id": request.param("id"),
+                        "filter": request.query("filter"),
+                        "agent": request.header("user-agent"),
+                        "session": request.cookie("session"),
+                        "peer": request.peer_addr().to_string(),
+                        "user": request.user(),
+                        "body": body,
+                    }))),
+                    None,
+                    200,
+                ))
+            },
+            false,
+        );
+    });
+
+    let body: Value = http_client()
+        .post(format!("{}/inspect/42?filter=new", server.base_url()))
+        .header("User-Agent", "router-test")
+        .header("Cookie", "theme=dark; session=abc123")
+        .body(vec![0u8, 159, 146, 150])
+        .send()
+        .expect("Request failed")
+        .json()
+        .expect("Failed to parse JSON");
+
+    assert_eq!(body["method"], "POST");
+    assert_eq!(body["path"], "/inspect/42");
+    assert_eq!(body["id"], "42");
+    assert_eq!(body["filter"], "new");
+    assert_eq!(body["agent"], "router-test");
+    assert_eq!(body["session"], "abc123");
+    assert_eq!(body["peer"], "127.0.0.1");
+    assert_eq!(body["user"], Value::Null);
+    assert_eq!(body["body"], json!([0, 159, 146, 150]));
+}
+
+#[test]
+fn request_root_defaults_to_current_directory_and_is_configurable() {
+    let root_route = |router: &mut scratch_server::Router| {
+        router.add_handler(
+            "/root",
+            HttpMethod::GET,
+            |request| Ok(text_response(&request.root().to_string_lossy())),
+            false,
+        );
+    };
+    let default_server = spawn_library_server(root_route);
+    let configured_server = spawn_configured_library_server(
+        |server| server.with_root(std::path::PathBuf::from("/srv/share")),
+        root_route,
+    );
+
+    for (server_url, expected) in [
+        (default_server.base_url(), "."),
+        (configured_server.base_url(), "/srv/share"),
+    ] {
+        let root = http_client()
+            .get(format!("{server_url}/root"))
+            .send()
+            .expect("Request failed")
+            .text()
+            .expect("Failed to read body");
+        assert_eq!(root, expected);
+    }
+}
+
+#[test]
+fn add_route_handlers_receive_text_body_and_merged_params() {
+    let server = spawn_library_server(|router| {
+        router.add_route(
+            "/legacy/{id}",
+            HttpMethod::POST,
+            |body, params| {
+                Ok(text_response(&format!(
+                    "{}:{}:{}",
+                    params.get("id").unwrap_or(&""),
+                    params.get("q").unwrap_or(&""),
+                    body.unwrap_or("")
+                )))
+            },
+            false,
+        );
+    });
+
+    let response = http_client()
+        .post(format!("{}/legacy/7?q=search", server.base_url()))
+        .body("payload")
+        .send()
+        .expect("Request failed");
+
+    assert_eq!(
+        response.text().expect("Failed to read body"),
+        "7:search:payload"
+    );
+}
@@ -0,0 +1,1186 @@
+use std::{fs, path::Path, thread, time::Duration};
+
+use base64::{prelude::BASE64_STANDARD, Engine};
+use serde_json::Value;
+
+use crate::common::utils::{
+    http_client, raw_connection, read_raw_head, read_raw_response, scratch_dir, send_raw,
+    spawn_server, spawn_server_in, TestServer,
+};
+
+fn multipart_body(boundary: &str, file_name: &str, content: &str) -> String {
+    format!(
+        "--{boundary}\r\n\
+        Content-Disposition: form-data; name=\"file\"; filename=\"{file_name}\"\r\n\
+        Content-Type: text/plain\r\n\r\n\
+        {content}\r\n--{boundary}--\r\n"
+    )
+}
+
+#[test]
+fn api_upload_writes_file_to_target_path() {
+    let server = spawn_server(&["--ip", "127.0.0.1"], false);
+    let (dir, relative) = scratch_dir("api_upload_writes_file");
+
+    let response = http_client()
+        .post(format!("{}/api/upload?path={relative}", server.base_url()))
+        .header("Content-Type", "multipart/form-data; boundary=XBOUNDARY")
+        .body(multipart_body("XBOUNDARY", "note.txt", "uploaded content"))
+        .send()
+        .expect("Upload request failed");
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        std::fs::read_to_string(dir.join("note.txt")).expect("Uploaded file missing"),
+        "uploaded content"
+    );
+}
+
+#[test]
+fn api_upload_without_path_returns_400() {
+    let server = spawn_server(&["--ip", "127.0.0.1"], false);
+
+    let response = http_client()
+        .post(format!("{}/api/upload", server.base_url()))
+        .header("Content-Type", "multipart/form-data; boundary=XBOUNDARY")
+        .body(multipart_body("XBOUNDARY", "note.txt", "uploaded content"))
+        .send()
+        .expect("Upload request failed");
+    assert_eq!(response.status().as_u16(), 400);
+
+    //the worker thread survives the bad request
+    let next = http_client()
+        .get(format!(
+            "{}/api/directory?path=tests/data",
+            server.base_url()
+        ))
+        .send()
+        .expect("Follow-up request failed");
+    assert_eq!(next.status().as_u16(), 200);
+}
+
+#[test]
+fn api_upload_requires_credentials_when_auth_enabled() {
+    let server = spawn_server(&["--ip", "127.0.0.1", "--auth", "user:pass"], false);
+    let (dir, relative) = scratch_dir("api_upload_requires_credentials");
+    let url = format!("{}/api/upload?path={relative}", server.base_url());
+
+    let unauthorized = http_client()
+        .post(&url)
+        .header("Content-Type", "multipart/form-data; boundary=XBOUNDARY")
+        .body(multipart_body("XBOUNDARY", "secret.txt", "content"))
+        .send()
+        .expect("Upload request failed");
+    assert_eq!(unauthorized.status().as_u16(), 401);
+    assert!(!dir.join("secret.txt").exists());
+
+    let authorized = http_client()
+        .post(&url)
+        .basic_auth("user", Some("pass"))
+        .header("Content-Type", "multipart/form-data; boundary=XBOUNDARY")
+        .body(multipart_body("XBOUNDARY", "secret.txt", "content"))
+        .send()
+        .expect("Upload request failed");
+    assert_eq!(authorized.status().as_u16(), 200);
+    assert!(dir.join("secret.txt").exists());
+}
+
+#[test]
+fn api_upload_skips_form_fields_before_the_file() {
+    let server = spawn_server(&["--ip", "127.0.0.1"], false);
+    let (dir, relative) = scratch_dir("api_upload_skips_form_fields");
+    let body = format!(
+        "--XBOUNDARY\r\n\
+        Content-Disposition: form-data; name=\"description\"\r\n\r\n\
+        release notes\r\n{}",
+        multipart_body("XBOUNDARY", "notes.txt", "line one\r\nline two\r\n")
+    );
+
+    let response = http_client()
+        .post(format!("{}/api/upload?path={relative}", server.base_url()))
+        .header("Content-Type", "multipart/form-data; boundary=XBOUNDARY")
+        .body(body)
+        .send()
+        .expect("Upload request failed");
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        std::fs::read_to_string(dir.join("notes.txt")).expect("Uploaded file missing"),
+        "line one\r\nline two\r\n"
+    );
+}
+
+#[test]
+fn api_upload_stores_multiple_files_and_folders() {
+    let server = spawn_server(&["--ip", "127.0.0.1"], false);
+    let (dir, relative) = scratch_dir("api_upload_multiple_files");
+    let body = format!(
+        "{}{}{}",
+        multipart_body("XBOUNDARY", "first.txt", "first"),
+        multipart_body("XBOUNDARY", "photos/2024/second.txt", "second"),
+        multipart_body("XBOUNDARY", "../escaped.txt", "escaped"),
+    )
+    .replace("--XBOUNDARY--\r\n--XBOUNDARY", "--XBOUNDARY");
+
+    let response = http_client()
+        .post(format!("{}/api/upload?path={relative}", server.base_url()))
+        .header("Content-Type", "multipart/form-data; boundary=XBOUNDARY")
+        .body(body)
+        .send()
+        .expect("Upload request failed");
+
+    assert_eq!(response.status().as_u16(), 200);
+    let report: Value = response.json().expect("Upload report is not JSON");
+    let files = report["files"].as_array().expect("Missing files array");
+    assert_eq!(files.len(), 3);
+    assert_eq!(files[0]["name"], "first.txt");
+    assert_eq!(files[0]["status"], "uploaded");
+    assert_eq!(files[0]["size"], 5);
+    assert_eq!(files[1]["name"], "photos/2024/second.txt");
+    assert_eq!(files[1]["status"], "uploaded");
+    assert_eq!(files[2]["name"], "../escaped.txt");
+    assert_eq!(files[2]["status"], "failed");
+    assert!(files[2]["error"].is_string());
+
+    assert_eq!(
+        std::fs::read_to_string(dir.join("first.txt")).expect("First file missing"),
+        "first"
+    );
+    assert_eq!(
+        std::fs::read_to_string(dir.join("photos/2024/second.txt")).expect("Nested file missing"),
+        "second"
+    );
+    assert!(!dir.parent().unwrap().join("escaped.txt").exists());
+}
+
+fn upload(server: &TestServer, query: &str, body: String) -> reqwest::blocking::Response {
+    http_client()
+        .post(format!("{}/api/upload?{query}", server.base_url()))
+        .header("Content-Type", "multipart/form-data; boundary=XBOUNDARY")
+        .body(body)
+        .send()
+        .expect("Upload request failed")
+}
+
+#[test]
+fn api_upload_overwrites_existing_files_by_default() {
+    let server = spawn_server(&["--ip", "127.0.0.1"], false);
+    let (dir, relative) = scratch_dir("api_upload_overwrites_by_default");
+    fs::write(dir.join("report.txt"), "old").unwrap();
+
+    let response = upload(
+        &server,
+        &format!("path={relative}"),
+        multipart_body("XBOUNDARY", "report.txt", "new"),
+    );
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(fs::read_to_string(dir.join("report.txt")).unwrap(), "new");
+}
+
+#[test]
+fn api_upload_rejects_existing_files_with_409() {
+    let server = spawn_server(&["--ip", "127.0.0.1"], false);
+    let (dir, relative) = scratch_dir("api_upload_rejects_existing");
+    fs::write(dir.join("report.txt"), "old").unwrap();
+    let body = format!(
+        "{}{}",
+        multipart_body("XBOUNDARY", "report.txt", "new"),
+        multipart_body("XBOUNDARY", "other.txt", "other"),
+    )
+    .replace("--XBOUNDARY--\r\n--XBOUNDARY", "--XBOUNDARY");
+
+    let response = upload(&server, &format!("path={relative}&conflict=reject"), body);
+
+    assert_eq!(response.status().as_u16(), 409);
+    let report: Value = response.json().expect("Upload report is not JSON");
+    assert_eq!(report["files"][0]["status"], "conflict");
+    assert_eq!(report["files"][1]["status"], "uploaded");
+    assert_eq!(fs::read_to_string(dir.join("report.txt")).unwrap(), "old");
+    assert_eq!(fs::read_to_string(dir.join("other.txt")).unwrap(), "other");
+}
+
+#[test]
+fn api_upload_renames_on_conflict() {
+    let server = spawn_server(&["--ip", "127.0.0.1"], false);
+    let (dir, relative) = scratch_dir("api_upload_renames_on_conflict");
+    fs::write(dir.join("report.txt"), "old").unwrap();
+    fs::write(dir.join("report (1).txt"), "older").unwrap();
+
+    let response = upload(
+        &server,
+        &format!("path={relative}&conflict=rename"),
+        multipart_body("XBOUNDARY", "report.txt", "new"),
+    );
+
+    assert_eq!(response.status().as_u16(), 200);
+    let report: Value = response.json().expect("Upload report is not JSON");
+    assert_eq!(report["files"][0]["stored_as"], "report (2).txt");
+    assert_eq!(fs::read_to_string(dir.join("report.txt")).unwrap(), "old");
+    assert_eq!(
+        fs::read_to_string(dir.join("report (2).txt")).unwrap(),
+        "new"
+    );
+}
+
+#[test]
+fn api_upload_uses_cli_conflict_default() {
+    let server = spawn_server(&["--ip", "127.0.0.1", "--on-conflict", "reject"], false);
+    let (dir, relative) = scratch_dir("api_upload_cli_conflict_default");
+    fs::write(dir.join("report.txt"), "old").unwrap();
+
+    let rejected = upload(
+        &server,
+        &format!("path={relative}"),
+        multipart_body("XBOUNDARY", "report.txt", "new"),
+    );
+    assert_eq!(rejected.status().as_u16(), 409);
+
+    let overwritten = upload(
+        &server,
+        &format!("path={relative}&conflict=overwrite"),
+        multipart_body("XBOUNDARY", "report.txt", "new"),
+    );
+    assert_eq!(overwritten.status().as_u16(), 200);
+    assert_eq!(fs::read_to_string(dir.join("report.txt")).unwrap(), "new");
+}
+
+#[test]
+fn api_upload_rejects_unknown_conflict_policy() {
+    let server = spawn_server(&["--ip", "127.0.0.1"], false);
+    let (_, relative) = scratch_dir("api_upload_unknown_conflict_policy");
+
+    let response = upload(
+        &server,
+        &format!("path={relative}&conflict=merge"),
+        multipart_body("XBOUNDARY", "report.txt", "new"),
+    );
+
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[test]
+fn interrupted_upload_leaves_existing_file_untouched() {
+    let server = spawn_server(&["--ip", "127.0.0.1"], false);
+    let (dir, relative) = scratch_dir("interrupted_upload");
+    fs::write(dir.join("report.txt"), "old").unwrap();
+
+    let body = multipart_body("XBOUNDARY", "report.txt", &"new content ".repeat(1000));
+    let mut connection = raw_connection(server.port);
+    send_raw(
+        &mut connection,
+        format!(
+            "POST /api/upload?path={relative} HTTP/1.1\r\n\
+            Host: localhost\r\n\
+            Content-Type: multipart/form-data; boundary=XBOUNDARY\r\n\
+            Content-Length: {}\r\n\r\n{}",
+            body.len(),
+            &body[..body.len() / 2]
+        )
+        .as_bytes(),
+    );
+    drop(connection);
+
+    //the server cleans up the temporary file once it notices the dropped connection
+    let mut entries = Vec::new();
+    for _ in 0..50 {
+        entries = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        if entries.len() == 1 {
+            break;
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+    assert_eq!(entries, vec!["report.txt"]);
+    assert_eq!(fs::read_to_string(dir.join("report.txt")).unwrap(), "old");
+}
+
+#[test]
+fn interrupted_put_leaves_existing_file_untouched() {
+    let server = spawn_server(&["--ip", "127.0.0.1"], false);
+    let (dir, relative) = scratch_dir("interrupted_put");
+    fs::write(dir.join("report.txt"), "old").unwrap();
+
+    let mut connection = raw_connection(server.port);
+    send_raw(
+        &mut connection,
+        format!(
+            "PUT /api/files?path={relative}/report.txt HTTP/1.1\r\n\
+            Host: localhost\r\n\
+            Content-Length: 1000\r\n\r\n\
+            new conte"
+        )
+        .as_bytes(),
+    );
+    drop(connection);
+
+    //a truncated body must not be mistaken for a complete one once the server notices
+    thread::sleep(Duration::from_millis(500));
+    let mut entries = Vec::new();
+    for _ in 0..50 {
+        entries = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        if entries.len() == 1 {
+            break;
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+    assert_eq!(entries, vec!["report.txt"]);
+    assert_eq!(fs::read_to_string(dir.join("report.txt")).unwrap(), "old");
+}
+
+#[test]
+fn upload_in_progress_is_hidden_and_not_counted_towards_quota() {
+    let (dir, _) = scratch_dir("upload_in_progress_hidden");
+    let mount = format!("drop={}", dir.to_str().unwrap());
+    let server = spawn_server(
+        &["--ip", "127.0.0.1", "--mount", &mount, "--quota", "drop=1K"],
+        false,
+    );
+
+    let mut connection = raw_connection(server.port);
+    send_raw(
+        &mut connection,
+        format!(
+            "PUT /api/files?path=drop/first.bin HTTP/1.1\r\n\
+            Host: localhost\r\n\
+            Content-Length: 800\r\n\r\n{}",
+            "x".repeat(600)
+        )
+        .as_bytes(),
+    );
+    thread::sleep(Duration::from_millis(300));
+
+    let listing: Value = http_client()
+        .get(format!("{}/api/directory?path=drop", server.base_url()))
+        .send()
+        .expect("GET request failed")
+        .json()
+        .expect("Listing is not JSON");
+    assert_eq!(listing["files"], serde_json::json!([]));
+    let visible = fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name())
+        .filter(|name| name != ".tus-staging")
+        .count();
+    assert_eq!(visible, 0);
+
+    //the 600 bytes still being received must not count against the 1K quota
+    let second = http_client()
+        .put(format!(
+            "{}/api/files?path=drop/second.bin",
+            server.base_url()
+        ))
+        .body("y".repeat(600))
+        .send()
+        .expect("PUT request failed");
+    assert_eq!(second.status().as_u16(), 201);
+    drop(connection);
+}
+
+fn put(server: &TestServer, query: &str, body: &'static str) -> reqwest::blocking::Response {
+    http_client()
+        .put(format!("{}/api/files?{query}", server.base_url()))
+        .body(body)
+        .send()
+        .expect("PUT request failed")
+}
+
+#[test]
+fn put_writes_raw_body_to_file() {
+    let server = spawn_server(&["--ip", "127.0.0.1"], false);
+    let (dir, relative) = scratch_dir("put_writes_raw_body");
+
+    let created = put(&server, &format!("path={relative}/build/out.bin"), "first");
+    assert_eq!(created.status().as_u16(), 201);
+    assert_eq!(
+        fs::read_to_string(dir.join("build/out.bin")).unwrap(),
+        "first"
+    );
+
+    let replaced = put(&server, &format!("path={relative}/build/out.bin"), "second");
+    assert_eq!(replaced.status().as_u16(), 200);
+    assert_eq!(
+        fs::read_to_string(dir.join("build/out.bin")).unwrap(),
+        "second"
+    );
+
+    let rejected = put(
+        &server,
+        &format!("path={relative}/build/out.bin&conflict=reject"),
+        "third",
+    );
+    assert_eq!(rejected.status().as_u16(), 409);
+    assert_eq!(
+        fs::read_to_string(dir.join("build/out.bin")).unwrap(),
+        "second"
+    );
+}
+
+#[test]
+fn concurrent_uploads_never_take_the_same_name() {
+    let server = spawn_server(&["--ip", "127.0.0.1", "--on-conflict", "rename"], false);
+    let (dir, relative) = scratch_dir("concurrent_uploads_same_name");
+    let bodies = [
+        "one", "two", "three", "four", "five", "six", "seven", "eight",
+    ];
+
+    thread::scope(|scope| {
+        for body in bodies {
+            let (server, relative) = (&server, &relative);
+            scope.spawn(move || {
+                let response = put(server, &format!("path={relative}/report.txt"), body);
+                assert_eq!(response.status().as_u16(), 201);
+            });
+        }
+    });
+
+    let mut stored = fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| fs::read_to_string(entry.unwrap().path()).unwrap())
+        .collect::<Vec<_>>();
+    stored.sort();
+    let mut expected = bodies.to_vec();
+    expected.sort();
+    assert_eq!(stored, expected);
+}
+
+#[test]
+fn put_rejects_paths_outside_the_served_directory() {
+    let server = spawn_server(&["--ip", "127.0.0.1"], false);
+
+    let response = put(&server, "path=../escaped.txt", "content");
+
+    assert_eq!(response.status().as_u16(), 400);
+    assert!(!std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("../escaped.txt")
+        .exists());
+}
+
+#[test]
+fn put_requires_credentials_when_auth_enabled() {
+    let server = spawn_server(&["--ip", "127.0.0.1", "--auth", "user:pass"], false);
+    let (dir, relative) = scratch_dir("put_requires_credentials");
+
+    let response = put(&server, &format!("path={relative}/secret.txt"), "content");
+
+    assert_eq!(response.status().as_u16(), 401);
+    assert!(!dir.join("secret.txt").exists());
+}
+
+#[test]
+fn put_answers_expect_continue_before_reading_body() {
+    let server = spawn_server(&["--ip", "127.0.0.1"], false);
+    let (dir, relative) = scratch_dir("put_expect_continue");
+
+    let mut connection = raw_connection(server.port);
+    send_raw(
+        &mut connection,
+        format!(
+            "PUT /api/files?path={relative}/upload.txt HTTP/1.1\r\n\
+            Host: localhost\r\n\
+            Content-Length: 7\r\n\
+            Expect: 100-continue\r\n\r\n"
+        )
+        .as_bytes(),
+    );
+    assert_eq!(read_raw_head(&mut connection).status, 100);
+
+    send_raw(&mut connection, b"content");
+    assert_eq!(read_raw_response(&mut connection).status, 201);
+    assert_eq!(
+        fs::read_to_string(dir.join("upload.txt")).unwrap(),
+        "content"
+    );
+}
+
+#[test]
+fn put_rejected_before_reading_body_skips_100_continue() {
+    let server = spawn_server(&["--ip", "127.0.0.1", "--auth", "user:pass"], false);
+    let (dir, relative) = scratch_dir("put_rejected_expect_continue");
+
+    let mut connection = raw_connection(server.port);
+    send_raw(
+        &mut connection,
+        format!(
+            "PUT /api/files?path={relative}/upload.txt HTTP/1.1\r\n\
+            Host: localhost\r\n\
+            Content-Length: 7\r\n\
+            Expect: 100-continue\r\n\r\n"
+        )
+        .as_bytes(),
+    );
+    let response = read_raw_response(&mut connection);
+
+    assert_eq!(response.status, 401);
+    assert_eq!(response.header("connection"), Some("close"));
+    assert!(!dir.join("upload.txt").exists());
+}
+
+#[test]
+fn put_over_upload_limit_is_answered_without_receiving_whole_body() {
+    let server = spawn_server(&["--ip", "127.0.0.1", "--max-upload-size", "1K"], false);
+    let (dir, relative) = scratch_dir("put_over_limit_not_drained");
+
+    let mut connection = raw_connection(server.port);
+    send_raw(
+        &mut connection,
+        format!(
+            "PUT /api/files?path={relative}/large.bin HTTP/1.1\r\n\
+            Host: localhost\r\n\
+            Content-Length: 209715200\r\n\r\n"
+        )
+        .as_bytes(),
+    );
+    //far less than announced, the server must answer without waiting for the rest
+    send_raw(&mut connection, &vec![b'x'; 512 * 1024]);
+    let response = read_raw_response(&mut connection);
+
+    assert_eq!(response.status, 413);
+    assert_eq!(response.header("connection"), Some("close"));
+    assert!(!dir.join("large.bin").exists());
+}
+
+fn tus_request(method: reqwest::Method, url: &str) -> reqwest::blocking::RequestBuilder {
+    http_client()
+        .request(method, url)
+        .header("Tus-Resumable", "1.0.0")
+}
+
+fn create_tus_upload(server: &TestServer, relative: &str, name: &str, length: u64) -> String {
+    let response = tus_request(
+        reqwest::Method::POST,
+        &format!("{}/api/tus?path={relative}", server.base_url()),
+    )
+    .header("Upload-Length", length.to_string())
+    .header(
+        "Upload-Metadata",
+        format!(
+            "filename {},filetype dGV4dC9wbGFpbg==",
+            BASE64_STANDARD.encode(name)
+        ),
+    )
+    .send()
+    .expect("Creation request failed");
+    assert_eq!(response.status().as_u16(), 201);
+    assert_eq!(response.headers()["tus-resumable"], "1.0.0");
+    let location = response.headers()["location"].to_str().unwrap();
+    format!("{}{location}", server.base_url())
+}
+
+fn tus_offset(url: &str) -> reqwest::blocking::Response {
+    tus_request(reqwest::Method::HEAD, url)
+        .send()
+        .expect("HEAD request failed")
+}
+
+fn tus_patch(url: &str, offset: u64, body: &'static str) -> reqwest::blocking::Response {
+    tus_request(reqwest::Method::PATCH, url)
+        .header("Content-Type", "application/offset+octet-stream")
+        .header("Upload-Offset", offset.to_string())
+        .body(body)
+        .send()
+        .expect("PATCH request failed")
+}
+
+#[test]
+fn tus_options_advertises_protocol() {
+    let server = spawn_server(&["--ip", "127.0.0.1"], false);
+
+    let response = http_client()
+        .request(
+            reqwest::Method::OPTIONS,
+            format!("{}/api/tus", server.base_url()),
+        )
+        .send()
+        .expect("OPTIONS request failed");
+
+    assert_eq!(response.status().as_u16(), 204);
+    assert_eq!(response.headers()["tus-version"], "1.0.0");
+    assert_eq!(response.headers()["tus-extension"], "creation,termination");
+}
+
+#[test]
+fn tus_upload_is_assembled_from_patches() {
+    let server = spawn_server(&["--ip", "127.0.0.1"], false);
+    let (dir, relative) = scratch_dir("tus_upload_assembled");
+    let url = create_tus_upload(&server, &relative, "artifact.txt", 11);
+
+    let head = tus_offset(&url);
+    assert_eq!(head.status().as_u16(), 200);
+    assert_eq!(head.headers()["upload-offset"], "0");
+    assert_eq!(head.headers()["upload-length"], "11");
+    assert_eq!(head.headers()["cache-control"], "no-store");
+
+    let first = tus_patch(&url, 0, "hello ");
+    assert_eq!(first.status().as_u16(), 204);
+    assert_eq!(first.headers()["upload-offset"], "6");
+    assert_eq!(tus_offset(&url).headers()["upload-offset"], "6");
+    assert!(!dir.join("artifact.txt").exists());
+
+    let stale = tus_patch(&url, 0, "hello ");
+    assert_eq!(stale.status().as_u16(), 409);
+
+    let last = tus_patch(&url, 6, "world");
+    assert_eq!(last.status().as_u16(), 204);
+    assert_eq!(last.headers()["upload-offset"], "11");
+    assert_eq!(
+        fs::read_to_string(dir.join("artifact.txt")).unwrap(),
+        "hello world"
+    );
+}
+
+#[test]
+fn tus_upload_resumes_after_dropped_connection() {
+    let server = spawn_server(&["--ip", "127.0.0.1"], false);
+    let (dir, relative) = scratch_dir("tus_upload_resumes");
+    let url = create_tus_upload(&server, &relative, "resumed.txt", 10);
+    let path = url.trim_start_matches(&server.base_url()).to_string();
+
+    let mut connection = raw_connection(server.port);
+    send_raw(
+        &mut connection,
+        format!(
+            "PATCH {path} HTTP/1.1\r\n\
+            Host: localhost\r\n\
+            Tus-Resumable: 1.0.0\r\n\
+            Content-Type: application/offset+octet-stream\r\n\
+            Upload-Offset: 0\r\n\
+            Content-Length: 10\r\n\r\n\
+            0123"
+        )
+        .as_bytes(),
+    );
+    thread::sleep(Duration::from_millis(200));
+    drop(connection);
+
+    //the upload stays locked until the server notices the dropped connection
+    let mut status = 0;
+    for _ in 0..50 {
+        let response = tus_offset(&url);
+        if response.headers()["upload-offset"] == "4" {
+            status = tus_patch(&url, 4, "456789").status().as_u16();
+            if status != 409 {
+                break;
+            }
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+    assert_eq!(status, 204);
+    assert_eq!(
+        fs::read_to_string(dir.join("resumed.txt")).unwrap(),
+        "0123456789"
+    );
+}
+
+#[test]
+fn tus_staging_is_not_reachable_through_the_share() {
+    let server = spawn_server(&["--ip", "127.0.0.1"], false);
+    let (dir, relative) = scratch_dir("tus_staging_not_reachable");
+    let url = create_tus_upload(&server, &relative, "partial.txt", 10);
+    assert_eq!(tus_patch(&url, 0, "hello").status().as_u16(), 204);
+    let id = url.rsplit('/').next().unwrap();
+
+    let staging_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join(".tus-staging");
+    assert!(staging_dir.join(format!("{id}.bin")).exists());
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+
+    let request = |method: reqwest::Method, endpoint: &str| {
+        http_client()
+            .request(method, format!("{}{endpoint}", server.base_url()))
+            .send()
+            .expect("Request failed")
+            .status()
+            .as_u16()
+    };
+    let listing: Value = http_client()
+        .get(format!("{}/api/directory?path=", server.base_url()))
+        .send()
+        .expect("GET request failed")
+        .json()
+        .expect("Listing is not JSON");
+    assert!(listing["files"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .all(|file| file["name"] != ".tus-staging"));
+    assert_eq!(
+        request(reqwest::Method::GET, "/api/directory?path=.tus-staging"),
+        404
+    );
+    assert_eq!(
+        request(
+            reqwest::Method::GET,
+            &format!("/api/files?path=.tus-staging/{id}.bin")
+        ),
+        404
+    );
+    assert_eq!(
+        request(
+            reqwest::Method::PUT,
+            &format!("/api/files?path=.tus-staging/{id}.json")
+        ),
+        400
+    );
+    assert_eq!(
+        request(
+            reqwest::Method::DELETE,
+            "/api/files?path=.tus-staging&recursive=true"
+        ),
+        404
+    );
+    assert_eq!(
+        request(
+            reqwest::Method::POST,
+            &format!("/api/files/copy?from=.tus-staging/{id}.bin&to={relative}/copy.bin")
+        ),
+        404
+    );
+    assert_eq!(
+        tus_offset(&url).headers()["upload-offset"],
+        "5",
+        "staged upload was modified"
+    );
+}
+
+#[test]
+fn tus_upload_reports_and_verifies_sha256() {
+    let server = spawn_server(&["--ip", "127.0.0.1"], false);
+    let (dir, relative) = scratch_dir("tus_upload_sha256");
+    let hello_sha256 = "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+    let create = |name: &str, sha256: &str| {
+        let response = tus_request(
+            reqwest::Method::POST,
+            &format!("{}/api/tus?path={relative}", server.base_url()),
+        )
+        .header("Upload-Length", "5")
+        .header(
+            "Upload-Metadata",
+            format!(
+                "filename {},sha256 {}",
+                BASE64_STANDARD.encode(name),
+                BASE64_STANDARD.encode(sha256)
+            ),
+        )
+        .send()
+        .expect("Creation request failed");
+        assert_eq!(response.status().as_u16(), 201);
+        let location = response.headers()["location"].to_str().unwrap();
+        format!("{}{location}", server.base_url())
+    };
+
+    let verified = tus_patch(&create("verified.txt", hello_sha256), 0, "hello");
+    assert_eq!(verified.status().as_u16(), 204);
+    assert_eq!(verified.headers()["upload-sha256"], hello_sha256);
+    assert_eq!(
+        fs::read_to_string(dir.join("verified.txt")).unwrap(),
+        "hello"
+    );
+
+    let url = create("corrupted.txt", hello_sha256);
+    let corrupted = tus_patch(&url, 0, "hallo");
+    assert_eq!(corrupted.status().as_u16(), 422);
+    assert_eq!(tus_offset(&url).status().as_u16(), 404);
+    assert!(!dir.join("corrupted.txt").exists());
+}
+
+#[test]
+fn tus_terminated_upload_is_gone() {
+    let server = spawn_server(&["--ip", "127.0.0.1"], false);
+    let (_, relative) = scratch_dir("tus_terminated_upload");
+    let url = create_tus_upload(&server, &relative, "cancelled.txt", 10);
+
+    let response = tus_request(reqwest::Method::DELETE, &url)
+        .send()
+        .expect("DELETE request failed");
+    assert_eq!(response.status().as_u16(), 204);
+
+    assert_eq!(tus_offset(&url).status().as_u16(), 404);
+}
+
+#[test]
+fn tus_requires_protocol_version() {
+    let server = spawn_server(&["--ip", "127.0.0.1"], false);
+    let (_, relative) = scratch_dir("tus_requires_version");
+
+    let response = http_client()
+        .post(format!("{}/api/tus?path={relative}", server.base_url()))
+        .header("Upload-Length", "10")
+        .header("Upload-Metadata", "filename YS50eHQ=")
+        .send()
+        .expect("Creation request failed");
+
+    assert_eq!(response.status().as_u16(), 412);
+    assert_eq!(response.headers()["tus-version"], "1.0.0");
+}
+
+#[test]
+fn uploads_over_max_size_are_rejected_with_413() {
+    let server = spawn_server(&["--ip", "127.0.0.1", "--max-upload-size", "10"], false);
+    let (dir, relative) = scratch_dir("uploads_over_max_size");
+
+    let fits = put(&server, &format!("path={relative}/fits.txt"), "0123456789");
+    assert_eq!(fits.status().as_u16(), 201);
+
+    let too_large = put(
+        &server,
+        &format!("path={relative}/large.txt"),
+        "0123456789a",
+    );
+    assert_eq!(too_large.status().as_u16(), 413);
+    assert!(!dir.join("large.txt").exists());
+
+    let multipart = upload(
+        &server,
+        &format!("path={relative}"),
+        multipart_body("XBOUNDARY", "large.txt", "0123456789a"),
+    );
+    assert_eq!(multipart.status().as_u16(), 413);
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+}
+
+#[test]
+fn uploads_over_directory_quota_are_reported() {
+    let (root, _) = scratch_dir("uploads_over_directory_quota");
+    fs::create_dir_all(root.join("limited")).unwrap();
+    fs::create_dir_all(root.join("free")).unwrap();
+    fs::write(root.join("limited/existing.txt"), "0123456789").unwrap();
+    let server = spawn_server_in(
+        &root,
+        &["--ip", "127.0.0.1", "--quota", "limited=16"],
+        false,
+    );
+
+    let body = format!(
+        "{}{}",
+        multipart_body("XBOUNDARY", "small.txt", "12345"),
+        multipart_body("XBOUNDARY", "big.txt", "1234567890"),
+    )
+    .replace("--XBOUNDARY--\r\n--XBOUNDARY", "--XBOUNDARY");
+    let response = upload(&server, "path=limited", body);
+
+    assert_eq!(response.status().as_u16(), 507);
+    let report: Value = response.json().expect("Upload report is not JSON");
+    assert_eq!(report["files"][0]["status"], "uploaded");
+    assert_eq!(report["files"][1]["status"], "quota_exceeded");
+    assert!(root.join("limited/small.txt").exists());
+    assert!(!root.join("limited/big.txt").exists());
+
+    let unlimited = put(&server, "path=free/big.txt", "01234567890123456789");
+    assert_eq!(unlimited.status().as_u16(), 201);
+}
+
+#[test]
+fn quota_counts_files_stored_earlier_in_the_same_request() {
+    let (root, _) = scratch_dir("quota_counts_earlier_files");
+    fs::create_dir_all(root.join("limited")).unwrap();
+    fs::write(root.join("limited/existing.txt"), "0123456789").unwrap();
+    let server = spawn_server_in(
+        &root,
+        &["--ip", "127.0.0.1", "--quota", "limited=16"],
+        false,
+    );
+
+    let body = format!(
+        "{}{}",
+        multipart_body("XBOUNDARY", "first.txt", "12345"),
+        multipart_body("XBOUNDARY", "second.txt", "12345"),
+    )
+    .replace("--XBOUNDARY--\r\n--XBOUNDARY", "--XBOUNDARY");
+    let response = upload(&server, "path=limited", body);
+
+    assert_eq!(response.status().as_u16(), 507);
+    let report: Value = response.json().expect("Upload report is not JSON");
+    assert_eq!(report["files"][0]["status"], "uploaded");
+    assert_eq!(report["files"][1]["status"], "quota_exceeded");
+    assert!(!root.join("limited/second.txt").exists());
+}
+
+const HELLO_SHA256: &str = "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9";
+const HELLO_SHA256_BASE64: &str = "uU0nuZNNPgilLlLX2n2r+sSE7+N6U4DukIj3rOLvzek=";
+
+#[test]
+fn put_reports_sha256_and_verifies_content_digest() {
+    let server = spawn_server(&["--ip", "127.0.0.1"], false);
+    let (dir, relative) = scratch_dir("put_verifies_content_digest");
+
+    let response = http_client()
+        .put(format!(
+            "{}/api/files?path={relative}/hello.txt",
+            server.base_url()
+        ))
+        .header("Content-Digest", format!("sha-256=:{HELLO_SHA256_BASE64}:"))
+        .body("hello world")
+        .send()
+        .expect("PUT request failed");
+    assert_eq!(response.status().as_u16(), 201);
+    let report: Value = response.json().expect("Upload report is not JSON");
+    assert_eq!(report["sha256"], HELLO_SHA256);
+
+    let mismatch = http_client()
+        .put(format!(
+            "{}/api/files?path={relative}/tampered.txt",
+            server.base_url()
+        ))
+        .header("Digest", format!("SHA-256={HELLO_SHA256_BASE64}"))
+        .body("hello world!")
+        .send()
+        .expect("PUT request failed");
+    assert_eq!(mismatch.status().as_u16(), 422);
+    let report: Value = mismatch.json().expect("Upload report is not JSON");
+    assert_eq!(report["status"], "checksum_mismatch");
+    assert!(dir.join("hello.txt").exists());
+    assert!(!dir.join("tampered.txt").exists());
+}
+
+#[test]
+fn multipart_sha256_field_verifies_following_file() {
+    let server = spawn_server(&["--ip", "127.0.0.1"], false);
+    let (dir, relative) = scratch_dir("multipart_sha256_field");
+    let body = format!(
+        "--XBOUNDARY\r\n\
+        Content-Disposition: form-data; name=\"sha256\"\r\n\r\n\
+        {HELLO_SHA256}\r\n{}{}{}",
+        multipart_body("XBOUNDARY", "verified.txt", "hello world"),
+        multipart_body("XBOUNDARY", "unchecked.txt", "anything"),
+        multipart_body("XBOUNDARY", "corrupted.txt", "hello w0rld"),
+    )
+    .replace("--XBOUNDARY--\r\n--XBOUNDARY", "--XBOUNDARY")
+    .replace(
+        "--XBOUNDARY\r\nContent-Disposition: form-data; name=\"file\"; filename=\"corrupted.txt\"",
+        &format!(
+            "--XBOUNDARY\r\nContent-Disposition: form-data; name=\"sha256\"\r\n\r\n{HELLO_SHA256}\r\n\
+            --XBOUNDARY\r\nContent-Disposition: form-data; name=\"file\"; filename=\"corrupted.txt\""
+        ),
+    );
+
+    let response = upload(&server, &format!("path={relative}"), body);
+
+    assert_eq!(response.status().as_u16(), 422);
+    let report: Value = response.json().expect("Upload report is not JSON");
+    assert_eq!(report["files"][0]["status"], "uploaded");
+    assert_eq!(report["files"][0]["sha256"], HELLO_SHA256);
+    assert_eq!(report["files"][1]["status"], "uploaded");
+    assert_eq!(report["files"][2]["status"], "checksum_mismatch");
+    assert!(dir.join("verified.txt").exists());
+    assert!(dir.join("unchecked.txt").exists());
+    assert!(!dir.join("corrupted.txt").exists());
+}
+
+#[test]
+fn upload_file_type_filters_check_extension_and_content() {
+    let server = spawn_server(
+        &[
+            "--ip",
+            "127.0.0.1",
+            "--deny-ext",
+            "exe,bat",
+            "--allow-mime",
+            "image/*,text/*",
+        ],
+        false,
+    );
+    let (dir, relative) = scratch_dir("upload_file_type_filters");
+    let body = [
+        multipart_body("XBOUNDARY", "notes.txt", "plain text"),
+        multipart_body("XBOUNDARY", "tool.exe", "MZ executable"),
+        multipart_body("XBOUNDARY", "cat.gif", "MZ renamed executable"),
+        multipart_body("XBOUNDARY", "dog.gif", "GIF89a picture"),
+        multipart_body("XBOUNDARY", "archive.tar", "not an image"),
+    ]
+    .join("")
+    .replace("--XBOUNDARY--\r\n--XBOUNDARY", "--XBOUNDARY");
+
+    let response = upload(&server, &format!("path={relative}"), body);
+
+    assert_eq!(response.status().as_u16(), 415);
+    let report: Value = response.json().expect("Upload report is not JSON");
+    let statuses: Vec<&str> = report["files"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|file| file["status"].as_str().unwrap())
+        .collect();
+    assert_eq!(
+        statuses,
+        vec![
+            "uploaded",
+            "unsupported_type",
+            "unsupported_type",
+            "uploaded",
+            "unsupported_type"
+        ]
+    );
+    let mut entries: Vec<String> = fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .collect();
+    entries.sort();
+    assert_eq!(entries, vec!["dog.gif", "notes.txt"]);
+}
+
+#[test]
+fn put_and_tus_reject_denied_file_types() {
+    let server = spawn_server(
+        &[
+            "--ip",
+            "127.0.0.1",
+            "--deny-ext",
+            ".EXE",
+            "--deny-mime",
+            "application/x-msdownload",
+        ],
+        false,
+    );
+    let (dir, relative) = scratch_dir("put_and_tus_reject_denied_types");
+
+    let response = put(&server, &format!("path={relative}/setup.txt"), "MZ payload");
+    assert_eq!(response.status().as_u16(), 415);
+    let report: Value = response.json().expect("Upload report is not JSON");
+    assert_eq!(report["status"], "unsupported_type");
+    assert!(!dir.join("setup.txt").exists());
+
+    let creation = tus_request(
+        reqwest::Method::POST,
+        &format!("{}/api/tus?path={relative}", server.base_url()),
+    )
+    .header("Upload-Length", "10")
+    .header(
+        "Upload-Metadata",
+        format!("filename {}", BASE64_STANDARD.encode("setup.exe")),
+    )
+    .send()
+    .expect("Creation request failed");
+    assert_eq!(creation.status().as_u16(), 415);
+
+    let url = create_tus_upload(&server, &relative, "setup.txt", 10);
+    let patch = tus_patch(&url, 0, "MZ payload");
+    assert_eq!(patch.status().as_u16(), 415);
+    assert_eq!(tus_offset(&url).status().as_u16(), 404);
+    assert!(!dir.join("setup.txt").exists());
+}
+
+#[test]
+fn upload_file_names_are_sanitized() {
+    let server = spawn_server(&["--ip", "127.0.0.1"], false);
+    let (dir, relative) = scratch_dir("upload_file_names_sanitized");
+    let body = [
+        multipart_body("XBOUNDARY", "bad\u{7}name<1>.txt", "control"),
+        multipart_body("XBOUNDARY", "CON.txt", "reserved"),
+        multipart_body("XBOUNDARY", "notes/report.txt. ", "trailing"),
+        multipart_body("XBOUNDARY", "photo\u{202e}gpj.exe", "bidi"),
+        multipart_body("XBOUNDARY", "plain.txt", "plain"),
+    ]
+    .join("")
+    .replace("--XBOUNDARY--\r\n--XBOUNDARY", "--XBOUNDARY");
+
+    let response = upload(&server, &format!("path={relative}"), body);
+
+    assert_eq!(response.status().as_u16(), 200);
+    let report: Value = response.json().expect("Upload report is not JSON");
+    assert_eq!(report["files"][0]["stored_as"], "badname_1_.txt");
+    assert_eq!(report["files"][1]["stored_as"], "_CON.txt");
+    assert_eq!(report["files"][2]["stored_as"], "notes/report.txt");
+    assert_eq!(report["files"][3]["stored_as"], "photogpj.exe");
+    assert!(report["files"][4].get("stored_as").is_none());
+    assert_eq!(
+        fs::read_to_string(dir.join("notes/report.txt")).unwrap(),
+        "trailing"
+    );
+    assert!(dir.join("badname_1_.txt").exists());
+    assert!(dir.join("_CON.txt").exists());
+    assert!(dir.join("photogpj.exe").exists());
+}
+
+#[test]
+fn upload_decodes_unicode_file_names_and_path_header() {
+    let server = spawn_server(&["--ip", "127.0.0.1"], false);
+    let (dir, relative) = scratch_dir("upload_unicode_file_names");
+    fs::create_dir_all(dir.join("über")).unwrap();
+    let body = "--XBOUNDARY\r\n\
+        Content-Disposition: form-data; name=\"file\"; filename=\"fallback.txt\"; \
+        filename*=UTF-8''caf%C3%A9%20%E2%82%AC.txt\r\n\r\n\
+        unicode\r\n--XBOUNDARY--\r\n";
+
+    let response = http_client()
+        .post(format!("{}/api/upload", server.base_url()))
+        .header("Content-Type", "multipart/form-data; boundary=XBOUNDARY")
+        .header("Path", format!("{relative}/%C3%BCber"))
+        .body(body)
+        .send()
+        .expect("Upload request failed");
+
+    assert_eq!(response.status().as_u16(), 200);
+    let report: Value = response.json().expect("Upload report is not JSON");
+    assert_eq!(report["files"][0]["name"], "café €.txt");
+    assert!(report["files"][0].get("stored_as").is_none());
+    assert_eq!(
+        fs::read_to_string(dir.join("über/café €.txt")).unwrap(),
+        "unicode"
+    );
+}
+
+#[test]
+fn upload_file_names_are_normalized_to_nfc() {
+    let server = spawn_server(&["--ip", "127.0.0.1"], false);
+    let (dir, relative) = scratch_dir("upload_nfc_file_names");
+
+    //"café.txt" with the accent as a combining character, as sent from macOS
+    let decomposed = put(&server, &format!("path={relative}/cafe%CC%81.txt"), "nfd");
+    assert_eq!(decomposed.status().as_u16(), 201);
+    let report: Value = decomposed.json().expect("Upload report is not JSON");
+    assert_eq!(report["stored_as"], format!("{relative}/café.txt"));
+
+    let composed = put(
+        &server,
+        &format!("path={relative}/caf%C3%A9.txt&conflict=reject"),
+        "nfc",
+    );
+    assert_eq!(composed.status().as_u16(), 409);
+    let entries = fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(entries, vec!["caf\u{e9}.txt"]);
+    assert_eq!(fs::read_to_string(dir.join("café.txt")).unwrap(), "nfd");
+}
+
+#[cfg(unix)]
+#[test]
+fn uploads_do_not_follow_symlinks_out_of_the_served_directory() {
+    let server = spawn_server(&["--ip", "127.0.0.1"], false);
+    let (dir, relative) = scratch_dir("uploads_symlink_escape");
+    let outside = std::env::temp_dir().join("scratch-server-outside-upload");
+    let _ = fs::remove_dir_all(&outside);
+    fs::create_dir_all(&outside).unwrap();
+    std::os::unix::fs::symlink(&outside, dir.join("linked_dir")).unwrap();
+
+    let response = put(
+        &server,
+        &format!("path={relative}/linked_dir/put.txt"),
+        "put",
+    );
+    assert_eq!(response.status().as_u16(), 400);
+
+    let response = upload(
+        &server,
+        &format!("path={relative}"),
+        multipart_body("XBOUNDARY", "linked_dir/multipart.txt", "multipart"),
+    );
+    assert_eq!(response.status().as_u16(), 200);
+    let report: Value = response.json().expect("Upload report is not JSON");
+    assert_eq!(report["files"][0]["status"], "failed");
+
+    let response = upload(
+        &server,
+        &format!("path={relative}/linked_dir"),
+        multipart_body("XBOUNDARY", "direct.txt", "direct"),
+    );
+    assert_eq!(response.status().as_u16(), 400);
+
+    assert_eq!(fs::read_dir(&outside).unwrap().count(), 0);
+}